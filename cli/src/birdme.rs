@@ -1,15 +1,285 @@
-pub struct Bird {
-    pub name: String,
-    pub scientific_name: String,
-    //TODO might be able to use a URL type here instead
-    pub link: String,
-    pub blurb: String,
+use birdme_types::ErrorBody;
+use reqwest::blocking::RequestBuilder;
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use std::fmt;
+
+use crate::config::Config;
+
+pub use birdme_types::{
+    html, Bird, BirdsQuery, Frequency, Hotspot, Observation, Region, SearchResult, SpeciesDetail,
+    TodaysBird,
+};
+
+#[derive(Debug)]
+pub enum BirdError {
+    // the server couldn't be reached at all
+    Connection(String),
+    // the server is throttling us, try again in a few seconds
+    RateLimited,
+    // the server didn't recognize the region code
+    BadRegion(String),
+    // the server turned down one of the options, with its explanation
+    BadQuery(String),
+    // the region has no species that fit, with the server's explanation
+    NoSpecies(String),
+    // the species, hotspot or region asked about doesn't exist
+    NotFound(String),
+    // any other request the server rejected, with its explanation
+    BadRequest(String),
+    // the server answered with a body we couldn't parse
+    Decode(String),
+    // any other non-success status from the server
+    Server(u16),
+}
+
+impl fmt::Display for BirdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BirdError::Connection(e) => write!(f, "unable to reach the birdme server: {}", e),
+            BirdError::RateLimited => write!(f, "too many requests, try again in a few seconds"),
+            BirdError::BadRegion(region) => {
+                write!(f, "{} isn't a region birdme knows about", region)
+            }
+            BirdError::BadQuery(message)
+            | BirdError::NoSpecies(message)
+            | BirdError::NotFound(message)
+            | BirdError::BadRequest(message) => write!(f, "{}", message),
+            BirdError::Decode(e) => write!(f, "couldn't read the birds sent by the server: {}", e),
+            BirdError::Server(status) => write!(f, "the birdme server responded with {}", status),
+        }
+    }
+}
+
+// used when neither the environment nor the config file name a server
+const DEFAULT_ENDPOINT: &str = "http://localhost:8000";
+
+// environment variable that overrides the endpoint from the config file
+const ENDPOINT_VAR: &str = "BIRDME_ENDPOINT";
+
+// endpoint picks the server to talk to. The environment variable wins over
+// the config file so a single run can be pointed at staging or a local server
+pub fn endpoint(config: &Config) -> String {
+    if let Ok(e) = std::env::var(ENDPOINT_VAR) {
+        if !e.is_empty() {
+            return e;
+        }
+    }
+
+    match &config.endpoint {
+        Some(e) => e.clone(),
+        None => String::from(DEFAULT_ENDPOINT),
+    }
+}
+
+// fetch_birds asks the server for a few random birds from the given region
+pub fn fetch_birds(
+    endpoint: &str,
+    region: &str,
+    query: &BirdsQuery,
+    lang: Option<&str>,
+) -> Result<Vec<Bird>, BirdError> {
+    let url = format!("{}/birds/{}", endpoint.trim_end_matches('/'), region);
+
+    let req = reqwest::blocking::Client::new().get(url).query(query);
+
+    send(with_lang(req, lang)).map_err(|e| in_region(e, region))
+}
+
+// fetch_today asks the server for the region's bird of the day
+pub fn fetch_today(
+    endpoint: &str,
+    region: &str,
+    lang: Option<&str>,
+) -> Result<TodaysBird, BirdError> {
+    let url = format!("{}/birds/{}/today", endpoint.trim_end_matches('/'), region);
+
+    send(with_lang(reqwest::blocking::Client::new().get(url), lang))
+        .map_err(|e| in_region(e, region))
+}
+
+// fetch_nearby asks the server for recent sightings around a point, within
+// dist kilometers when given
+pub fn fetch_nearby(
+    endpoint: &str,
+    lat: f64,
+    lng: f64,
+    dist: Option<u16>,
+    lang: Option<&str>,
+) -> Result<Vec<Observation>, BirdError> {
+    let url = format!("{}/birds/near", endpoint.trim_end_matches('/'));
+
+    send(with_lang(
+        reqwest::blocking::Client::new()
+            .get(url)
+            .query(&geo_query(lat, lng, dist)),
+        lang,
+    ))
+}
+
+// fetch_species looks up one bird by species code, banding code or name,
+// with recent sightings in region when one is given
+pub fn fetch_species(
+    endpoint: &str,
+    query: &str,
+    region: Option<&str>,
+    lang: Option<&str>,
+) -> Result<SpeciesDetail, BirdError> {
+    let url = endpoint_url(endpoint, &["species", query])?;
+
+    let mut req = reqwest::blocking::Client::new().get(url);
+    if let Some(r) = region {
+        req = req.query(&[("region", r)]);
+    }
+
+    send(with_lang(req, lang)).map_err(|e| match region {
+        Some(r) => in_region(e, r),
+        None => e,
+    })
+}
+
+// fetch_frequency asks how often a species turns up in region through the year
+pub fn fetch_frequency(endpoint: &str, query: &str, region: &str) -> Result<Frequency, BirdError> {
+    let url = endpoint_url(endpoint, &["species", query, "frequency"])?;
+
+    send(
+        reqwest::blocking::Client::new()
+            .get(url)
+            .query(&[("region", region)]),
+    )
+    .map_err(|e| in_region(e, region))
+}
+
+// fetch_search finds species matching a name, banding code or family
+pub fn fetch_search(endpoint: &str, query: &str) -> Result<Vec<SearchResult>, BirdError> {
+    let url = format!("{}/search", endpoint.trim_end_matches('/'));
+
+    let req = reqwest::blocking::Client::new().get(url);
+    send(req.query(&[("q", query)]))
+}
+
+// fetch_hotspots lists the hotspots in a region
+pub fn fetch_hotspots(endpoint: &str, region: &str) -> Result<Vec<Hotspot>, BirdError> {
+    let url = format!("{}/hotspots/{}", endpoint.trim_end_matches('/'), region);
+
+    send(reqwest::blocking::Client::new().get(url)).map_err(|e| in_region(e, region))
+}
+
+// fetch_nearby_hotspots lists the hotspots around a point, within dist
+// kilometers when given
+pub fn fetch_nearby_hotspots(
+    endpoint: &str,
+    lat: f64,
+    lng: f64,
+    dist: Option<u16>,
+) -> Result<Vec<Hotspot>, BirdError> {
+    let url = format!("{}/hotspots/near", endpoint.trim_end_matches('/'));
+
+    send(
+        reqwest::blocking::Client::new()
+            .get(url)
+            .query(&geo_query(lat, lng, dist)),
+    )
+}
+
+// fetch_hotspot looks up a single hotspot by its eBird location id
+pub fn fetch_hotspot(endpoint: &str, loc_id: &str) -> Result<Hotspot, BirdError> {
+    let url = format!(
+        "{}/hotspots/info/{}",
+        endpoint.trim_end_matches('/'),
+        loc_id
+    );
+
+    send(reqwest::blocking::Client::new().get(url))
+}
+
+// fetch_regions searches the regions directly under parent (every country
+// when there's no parent) for names containing the given text
+pub fn fetch_regions(
+    endpoint: &str,
+    parent: Option<&str>,
+    name: &str,
+) -> Result<Vec<Region>, BirdError> {
+    let url = format!("{}/regions", endpoint.trim_end_matches('/'));
+
+    let mut query = vec![("q", name)];
+    if let Some(p) = parent {
+        query.push(("parent", p));
+    }
+
+    send(reqwest::blocking::Client::new().get(url).query(&query)).map_err(|e| match parent {
+        Some(p) => in_region(e, p),
+        None => e,
+    })
+}
+
+// endpoint_url adds path segments to the endpoint, escaping them so a name
+// like "Greater/Lesser Yellowlegs" stays a single segment
+fn endpoint_url(endpoint: &str, segments: &[&str]) -> Result<Url, BirdError> {
+    let mut url =
+        Url::parse(endpoint).map_err(|e| BirdError::Connection(format!("{}: {}", endpoint, e)))?;
+    url.path_segments_mut()
+        .map_err(|_| BirdError::Connection(format!("{} can't take a path", endpoint)))?
+        .pop_if_empty()
+        .extend(segments);
+
+    Ok(url)
+}
+
+// with_lang asks for names and blurbs in lang, leaving the server to go by
+// its default when there isn't one
+fn with_lang(req: RequestBuilder, lang: Option<&str>) -> RequestBuilder {
+    match lang {
+        Some(l) => req.query(&[("lang", l)]),
+        None => req,
+    }
+}
+
+fn geo_query(lat: f64, lng: f64, dist: Option<u16>) -> Vec<(&'static str, String)> {
+    let mut query = vec![("lat", lat.to_string()), ("lng", lng.to_string())];
+    if let Some(d) = dist {
+        query.push(("dist", d.to_string()));
+    }
+
+    query
+}
+
+// in_region puts the region that was asked about into a BadRegion, in place
+// of the server's message
+fn in_region(err: BirdError, region: &str) -> BirdError {
+    match err {
+        BirdError::BadRegion(_) => BirdError::BadRegion(region.to_owned()),
+        e => e,
+    }
 }
 
-pub struct BirdError {}
+// send makes the request and decodes the JSON body, turning the server's
+// error statuses into BirdErrors
+fn send<T: DeserializeOwned>(req: RequestBuilder) -> Result<T, BirdError> {
+    let res = req
+        .send()
+        .map_err(|e| BirdError::Connection(e.to_string()))?;
 
-const BIRDME_ENDPOINT: &str = "";
+    let status = res.status();
+    if !status.is_success() {
+        return Err(match status.as_u16() {
+            429 => BirdError::RateLimited,
+            400 | 404 => match res.json::<ErrorBody>() {
+                // the server names the error, so a bad option isn't mistaken
+                // for a bad region
+                Ok(body) => match body.error.as_str() {
+                    "bad_region" => BirdError::BadRegion(body.message),
+                    "bad_query" => BirdError::BadQuery(body.message),
+                    "no_species" => BirdError::NoSpecies(body.message),
+                    "not_found" => BirdError::NotFound(body.message),
+                    _ => BirdError::BadRequest(body.message),
+                },
+                Err(_) => BirdError::BadRequest(status.to_string()),
+            },
+            s => BirdError::Server(s),
+        });
+    }
 
-pub fn fetch_birds() -> Result<Vec<Bird>, BirdError> {
-    unimplemented!()
+    res.json::<T>()
+        .map_err(|e| BirdError::Decode(e.to_string()))
 }
@@ -1,40 +1,73 @@
 use std::fs;
+use std::path::PathBuf;
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Serialize)]
+#[derive(Default, Deserialize, Serialize)]
 pub struct Config {
     pub region: Option<String>,
+    // base url of the birdme server, e.g. http://localhost:8000
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    // language for bird names and blurbs, e.g. es or fr
+    #[serde(default)]
+    pub lang: Option<String>,
 }
 
 impl Config {
     pub fn new() -> Self {
         // TODO try to get default region here
-        Config { region: None }
+        Config {
+            region: None,
+            endpoint: None,
+            lang: None,
+        }
     }
 }
 
-const CONFIG_FILE: &str = "~/.birdme/config.json";
+// the config lives at ~/.birdme/config.json
+const CONFIG_DIR: &str = ".birdme";
+const CONFIG_FILE: &str = "config.json";
+
+// config_path builds the config file location from the home directory, since
+// the filesystem calls don't expand ~ for us
+fn config_path() -> PathBuf {
+    let home = std::env::var("HOME")
+        .or_else(|_| std::env::var("USERPROFILE"))
+        .unwrap_or_default();
+
+    PathBuf::from(home).join(CONFIG_DIR).join(CONFIG_FILE)
+}
 
 // get_config reads the config file from the filesystem into a Config
 pub fn get_config() -> Option<Config> {
-    match fs::read_to_string(CONFIG_FILE) {
+    match fs::read_to_string(config_path()) {
         // TODO maybe update this one to handle json parsing errors
         Ok(contents) => serde_json::from_str(&contents).unwrap(),
+        // no config yet is the normal first run, not worth a word, least of
+        // all in the middle of someone's MOTD
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
         Err(e) => {
-            println!("Unable to read config file, {:?}", e);
+            eprintln!("Unable to read config file, {:?}", e);
             None
         }
     }
 }
 
+// update_region keeps whatever else is in the config file and only swaps
+// out the region
 pub fn update_region(region: String) -> Result<(), std::io::Error> {
-    let mut config = Config::new();
+    let mut config = get_config().unwrap_or_default();
     config.region = Some(region);
 
     write_config(config)
 }
 
 fn write_config(conf: Config) -> Result<(), std::io::Error> {
-    std::fs::write(CONFIG_FILE, serde_json::to_string_pretty(&conf).unwrap())
+    let path = config_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    std::fs::write(path, serde_json::to_string_pretty(&conf).unwrap())
 }
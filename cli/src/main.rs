@@ -1,75 +1,594 @@
-use cli::birdme::Bird;
+use clap::{Parser, Subcommand};
+use cli::birdme::{self, html, Bird, Hotspot};
 use cli::config;
 use std::io;
 
+#[derive(Parser)]
+#[command(name = "birdme", about = "Learn about the birds around you")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Latitude to look around instead of the configured region
+    #[arg(long, global = true, requires = "lng", allow_hyphen_values = true)]
+    lat: Option<f64>,
+
+    /// Longitude to look around instead of the configured region
+    #[arg(long, global = true, requires = "lat", allow_hyphen_values = true)]
+    lng: Option<f64>,
+
+    /// How far to look around --lat/--lng, in kilometers
+    #[arg(long, global = true, requires = "lat")]
+    dist: Option<u16>,
+
+    /// Language for bird names and blurbs, e.g. es or fr. Defaults to the
+    /// one in your config, then English
+    #[arg(long, global = true)]
+    lang: Option<String>,
+
+    /// Print birds as Markdown, keeping the article's emphasis and links
+    #[arg(long, global = true)]
+    markdown: bool,
+
+    /// Cut Wikipedia blurbs down to about this many characters
+    #[arg(long, global = true, value_parser = clap::value_parser!(u16).range(20..))]
+    blurb_length: Option<u16>,
+
+    /// How many birds to pick from your region
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=20))]
+    count: Option<u8>,
+
+    /// Only pick birds from a family, e.g. warbler or Parulidae
+    #[arg(long)]
+    family: Option<String>,
+
+    /// Only pick one kind of eBird taxon, e.g. species to leave out spuhs
+    /// and hybrids
+    #[arg(long)]
+    category: Option<String>,
+
+    /// Pick the same birds every time, e.g. so a whole class sees the same ones
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Favour birds that have actually been seen around lately
+    #[arg(long)]
+    weighted: bool,
+
+    /// From 0 for common birds to 1 for uncommon ones, implies --weighted
+    #[arg(long, value_parser = parse_rarity)]
+    rarity: Option<f64>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the birding hotspots in your region, or show a single one
+    Hotspots {
+        /// eBird location id of a hotspot to show, e.g. L109516
+        id: Option<String>,
+    },
+    /// Show your region's bird of the day, handy in a shell MOTD
+    Today,
+    /// Show everything about one bird
+    Show {
+        /// Common name, scientific name, species code or banding code,
+        /// e.g. "american robin", amerob or AMRO
+        #[arg(required = true)]
+        name: Vec<String>,
+
+        /// Region to summarize recent sightings in, defaults to your region
+        #[arg(long)]
+        region: Option<String>,
+    },
+    /// Chart which weeks of the year a bird turns up in your region
+    Frequency {
+        /// Common name, scientific name, species code or banding code
+        #[arg(required = true)]
+        name: Vec<String>,
+
+        /// Region to chart, defaults to your region
+        #[arg(long)]
+        region: Option<String>,
+    },
+    /// Search birds by name, banding code or family
+    Search {
+        /// Part of a name, a banding code or a family, e.g. "warbler" or AMRO
+        #[arg(required = true)]
+        query: Vec<String>,
+    },
+    /// Find and set the eBird region birdme looks in
+    Region {
+        #[command(subcommand)]
+        command: RegionCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum RegionCommand {
+    /// Search regions by name and save the one you pick
+    Search {
+        /// Part of the region's name, e.g. "new york"
+        name: String,
+
+        /// Region to search inside, e.g. US to search its states. Searches
+        /// countries when left out
+        #[arg(long)]
+        parent: Option<String>,
+    },
+}
+
 fn main() {
-    println!("Welcome to birdme, I hope you enjoy learning about some of your local birds!");
+    let args = Cli::parse();
+
+    // today's output is meant to be dropped into other things as is
+    if !matches!(args.command, Some(Command::Today)) {
+        println!("Welcome to birdme, I hope you enjoy learning about some of your local birds!");
+    }
 
     let config = match config::get_config() {
         Some(conf) => conf,
         None => config::Config::new(),
     };
-    // TODO put the setting of the region onto the user
-    let birds = fetch_birds(&config.region.expect("region must be set"));
+    let endpoint = birdme::endpoint(&config);
+    let lang = args.lang.or(config.lang.clone());
+    let format = BirdFormat {
+        markdown: args.markdown,
+        blurb_length: args.blurb_length.map(usize::from),
+    };
+    let point = match (args.lat, args.lng) {
+        (Some(lat), Some(lng)) => Some((lat, lng)),
+        _ => None,
+    };
+
+    match args.command {
+        Some(Command::Hotspots { id: Some(id) }) => show_hotspot(&endpoint, &id),
+        Some(Command::Hotspots { id: None }) => {
+            let spots = match point {
+                Some((lat, lng)) => birdme::fetch_nearby_hotspots(&endpoint, lat, lng, args.dist),
+                None => birdme::fetch_hotspots(&endpoint, &region(&config)),
+            };
+            match spots {
+                Ok(spots) => list_hotspots(spots),
+                Err(err) => exit_with(err),
+            }
+        }
+        Some(Command::Today) => {
+            match birdme::fetch_today(&endpoint, &region(&config), lang.as_deref()) {
+                Ok(today) => {
+                    println!("Bird of the day for {}:", today.date);
+                    show_bird(&today.bird, format);
+                }
+                Err(err) => exit_with(err),
+            }
+        }
+        Some(Command::Show { name, region }) => {
+            let region = region.or(config.region.clone());
+            show_species(
+                &endpoint,
+                &name.join(" "),
+                region.as_deref(),
+                lang.as_deref(),
+                format,
+            );
+        }
+        Some(Command::Frequency { name, region }) => {
+            let region = region.unwrap_or_else(|| self::region(&config));
+            show_frequency(&endpoint, &name.join(" "), &region);
+        }
+        Some(Command::Search { query }) => search_species(&endpoint, &query.join(" ")),
+        Some(Command::Region {
+            command: RegionCommand::Search { name, parent },
+        }) => search_region(&endpoint, &name, parent.as_deref()),
+        None => match point {
+            Some((lat, lng)) => show_nearby(&endpoint, lat, lng, args.dist, lang.as_deref()),
+            None => {
+                let query = birdme::BirdsQuery {
+                    count: args.count.map(usize::from),
+                    family: args.family,
+                    category: args.category,
+                    seed: args.seed,
+                    weighted: args.weighted.then_some(true),
+                    rarity: args.rarity,
+                };
+                let birds =
+                    match birdme::fetch_birds(&endpoint, &region(&config), &query, lang.as_deref())
+                    {
+                        Ok(birds) => birds,
+                        Err(err) => exit_with(err),
+                    };
+
+                choose_bird(&birds, format);
+            }
+        },
+    }
+}
+
+fn parse_rarity(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(r) if (0.0..=1.0).contains(&r) => Ok(r),
+        _ => Err(String::from("rarity must be a number between 0 and 1")),
+    }
+}
+
+// region pulls the configured region, bailing out when there isn't one
+fn region(config: &config::Config) -> String {
+    match &config.region {
+        Some(region) => region.clone(),
+        None => {
+            println!("No region is set, run `birdme region search <name>` to pick one");
+            println!("or pass --lat and --lng to look around a point instead");
+            std::process::exit(1);
+        }
+    }
+}
+
+// show_nearby lists what's been seen recently around the given point
+fn show_nearby(endpoint: &str, lat: f64, lng: f64, dist: Option<u16>, lang: Option<&str>) {
+    let observations = match birdme::fetch_nearby(endpoint, lat, lng, dist, lang) {
+        Ok(obs) => obs,
+        Err(err) => exit_with(err),
+    };
+
+    if observations.is_empty() {
+        println!("Nothing has been reported near you lately");
+        return;
+    }
+
+    println!("Recently seen near you:");
+    for obs in observations {
+        let count = match obs.count {
+            Some(c) => c.to_string(),
+            None => String::from("X"),
+        };
+        println!(
+            "{} {} ({}) at {} on {}",
+            count, obs.name, obs.scientific_name, obs.location, obs.observed_on
+        );
+    }
+}
+
+// list_hotspots prints hotspots with the most species first
+fn list_hotspots(mut spots: Vec<Hotspot>) {
+    if spots.is_empty() {
+        println!("No hotspots found");
+        return;
+    }
+
+    spots.sort_by_key(|spot| std::cmp::Reverse(spot.species_count));
+
+    for spot in spots {
+        let count = match spot.species_count {
+            Some(c) => format!("{} species", c),
+            None => String::from("no species yet"),
+        };
+        println!("{} {} ({})", spot.id, spot.name, count);
+    }
+}
+
+fn show_hotspot(endpoint: &str, id: &str) {
+    let spot = match birdme::fetch_hotspot(endpoint, id) {
+        Ok(spot) => spot,
+        Err(err) => exit_with(err),
+    };
+
+    println!();
+    println!("{} ({})", spot.name, spot.id);
+    println!(
+        "Located at {}, {} in {}",
+        spot.lat, spot.lng, spot.subnational1
+    );
+    if let Some(count) = spot.species_count {
+        println!("{} species seen here all time", count);
+    }
+    if let Some(latest) = spot.latest_observation {
+        println!("Last checklist on {}", latest);
+    }
+}
+
+fn show_species(
+    endpoint: &str,
+    name: &str,
+    region: Option<&str>,
+    lang: Option<&str>,
+    format: BirdFormat,
+) {
+    let detail = match birdme::fetch_species(endpoint, name, region, lang) {
+        Ok(detail) => detail,
+        Err(err) => exit_with(err),
+    };
+
+    println!();
+    show_bird(&detail.bird, format);
+
+    if let Some(s) = detail.sightings {
+        println!();
+        match s.latest {
+            Some(latest) => println!(
+                "Reported at {} locations in {} over the last {} days ({} birds counted), most recently on {}",
+                s.locations, s.region, s.days, s.birds_counted, latest
+            ),
+            None => println!(
+                "Not reported in {} over the last {} days",
+                s.region, s.days
+            ),
+        }
+    }
+}
+
+// rows in the frequency chart, each one a quarter of the sampled days
+const CHART_HEIGHT: usize = 4;
+
+// show_frequency draws a bar chart of the weeks a bird turns up in a region
+fn show_frequency(endpoint: &str, name: &str, region: &str) {
+    let frequency = match birdme::fetch_frequency(endpoint, name, region) {
+        Ok(f) => f,
+        Err(err) => exit_with(err),
+    };
+
+    let years = match (frequency.years.first(), frequency.years.last()) {
+        (Some(first), Some(last)) if first != last => format!("{}-{}", first, last),
+        (Some(year), _) => year.to_string(),
+        _ => String::new(),
+    };
+    println!(
+        "{} in {}, share of days reported in {} ({} days behind each week, so shares go in steps of {:.0}%)",
+        frequency.name,
+        frequency.region,
+        years,
+        frequency.days_per_week,
+        100.0 / frequency.days_per_week.max(1) as f32
+    );
+    for line in bar_chart(&frequency.weeks) {
+        println!("{}", line);
+    }
+}
+
+// bar_chart draws one column per week, scaled so a full column is every
+// sampled day. Weeks that round down to nothing still get a dot so a single
+// sighting isn't lost
+fn bar_chart(weeks: &[f32]) -> Vec<String> {
+    let heights: Vec<usize> = weeks
+        .iter()
+        .map(|w| (w * CHART_HEIGHT as f32).round() as usize)
+        .collect();
+
+    let mut lines = vec![];
+    for row in (1..=CHART_HEIGHT).rev() {
+        let label = match row {
+            CHART_HEIGHT => "100%",
+            r if r * 2 == CHART_HEIGHT => " 50%",
+            _ => "    ",
+        };
+        let bars: String = heights
+            .iter()
+            .zip(weeks)
+            .map(|(&h, &w)| match h >= row {
+                true => '#',
+                false if row == 1 && w > 0.0 => '.',
+                false => ' ',
+            })
+            .collect();
+        lines.push(format!("{} |{}", label, bars));
+    }
+
+    lines.push(format!("     +{}", "-".repeat(weeks.len())));
+    let months: String = "JFMAMJJASOND"
+        .chars()
+        .map(|m| format!("{:<4}", m))
+        .collect();
+    lines.push(format!("      {}", months.trim_end()));
+
+    lines
+}
+
+// search_species lists the birds matching query along with the codes that
+// can be passed to `birdme show`
+fn search_species(endpoint: &str, query: &str) {
+    let results = match birdme::fetch_search(endpoint, query) {
+        Ok(results) => results,
+        Err(err) => exit_with(err),
+    };
+
+    if results.is_empty() {
+        println!("No birds matched {}", query);
+        return;
+    }
+
+    for r in results {
+        let codes = match r.banding_codes.is_empty() {
+            true => r.species_code,
+            false => format!("{}, {}", r.species_code, r.banding_codes.join(", ")),
+        };
+        println!(
+            "{} ({}) - {} [{}]",
+            r.name, r.scientific_name, r.family_name, codes
+        );
+    }
+}
+
+// search_region lets the user pick a region from the ones matching name and
+// saves it to the config file
+fn search_region(endpoint: &str, name: &str, parent: Option<&str>) {
+    let regions = match birdme::fetch_regions(endpoint, parent, name) {
+        Ok(regions) => regions,
+        Err(err) => exit_with(err),
+    };
+
+    if regions.is_empty() {
+        println!("No regions matched {}", name);
+        if parent.is_none() {
+            println!("Only countries were searched, pass --parent US to search its states");
+        }
+        return;
+    }
+
+    println!("Please select your region:");
+    for (i, region) in regions.iter().enumerate() {
+        println!("{}. {} ({})", i + 1, region.name, region.code);
+    }
+
+    let region = match pick(regions.len()) {
+        Some(i) => &regions[i],
+        None => return,
+    };
+
+    if let Err(err) = config::update_region(region.code.clone()) {
+        println!("Unable to save the region: {}", err);
+        std::process::exit(1);
+    }
+
+    println!("Region set to {} ({})", region.name, region.code);
+}
 
+// choose_bird lists the birds and shows more about the one the user picks
+fn choose_bird(birds: &[Bird], format: BirdFormat) {
     println!("Please select a bird below to learn more:");
     for (i, bird) in birds.iter().enumerate() {
         println!("{}. {} ({})", i + 1, bird.name, bird.scientific_name);
     }
 
+    if let Some(i) = pick(birds.len()) {
+        println!();
+        show_bird(&birds[i], format);
+    }
+}
+
+// BirdFormat is how show_bird prints a bird, from --markdown and
+// --blurb-length
+#[derive(Clone, Copy)]
+struct BirdFormat {
+    markdown: bool,
+    blurb_length: Option<usize>,
+}
+
+fn show_bird(bird: &Bird, format: BirdFormat) {
+    if format.markdown {
+        println!("{}", bird_markdown(bird, format.blurb_length));
+        return;
+    }
+
+    println!("{} ({})", bird.name, bird.scientific_name);
+    println!(
+        "Family: {} ({}), order {}",
+        bird.family_name, bird.family_scientific_name, bird.order
+    );
+    if let Some(description) = &bird.description {
+        println!("{}", description);
+    }
+    if let Some(blurb) = &bird.blurb {
+        match format.blurb_length {
+            Some(max) => println!("{}", html::truncate(blurb, max)),
+            None => println!("{}", blurb),
+        }
+    }
+    if let Some(image) = &bird.image {
+        println!("Photo: {}", image.url);
+    }
+    if let Some(link) = &bird.link {
+        println!("To learn more visit {}", link);
+    }
+    println!(
+        "See sightings at https://ebird.org/species/{}",
+        bird.species_code
+    );
+}
+
+// bird_markdown lays a bird out as a Markdown section, e.g. for notes or a
+// class handout
+fn bird_markdown(bird: &Bird, blurb_length: Option<usize>) -> String {
+    let mut blocks = vec![
+        format!("## {} (*{}*)", bird.name, bird.scientific_name),
+        format!(
+            "Family: {} (*{}*), order {}",
+            bird.family_name, bird.family_scientific_name, bird.order
+        ),
+    ];
+    if let Some(description) = &bird.description {
+        blocks.push(description.clone());
+    }
+    if let Some(blurb) = markdown_blurb(bird, blurb_length) {
+        blocks.push(blurb);
+    }
+    if let Some(image) = &bird.image {
+        blocks.push(format!("![{}]({})", bird.name, image.url));
+    }
+
+    let mut links = vec![];
+    if let Some(link) = &bird.link {
+        links.push(format!("[Wikipedia]({})", link));
+    }
+    links.push(format!(
+        "[eBird](https://ebird.org/species/{})",
+        bird.species_code
+    ));
+    blocks.push(links.join(" · "));
+
+    blocks.join("\n\n")
+}
+
+// markdown_blurb renders the blurb's html when there is some. Cutting
+// Markdown mid paragraph could leave emphasis or a link unclosed, so a limit
+// keeps whole paragraphs, falling back to the plain text cut short when not
+// even the first fits
+fn markdown_blurb(bird: &Bird, blurb_length: Option<usize>) -> Option<String> {
+    let plain = || {
+        let blurb = bird.blurb.as_deref()?;
+        Some(match blurb_length {
+            Some(max) => html::truncate(blurb, max),
+            None => blurb.to_owned(),
+        })
+    };
+
+    let markdown = match &bird.blurb_html {
+        Some(h) => html::to_markdown(h),
+        None => return plain(),
+    };
+    let max = match blurb_length {
+        Some(max) => max,
+        None => return Some(markdown),
+    };
+
+    let mut kept = String::new();
+    for paragraph in markdown.split("\n\n") {
+        let separator = if kept.is_empty() { "" } else { "\n\n" };
+        if kept.chars().count() + separator.len() + paragraph.chars().count() > max {
+            break;
+        }
+        kept.push_str(separator);
+        kept.push_str(paragraph);
+    }
+
+    match kept.is_empty() {
+        true => plain(),
+        false => Some(kept),
+    }
+}
+
+// pick reads a 1 based selection from stdin and hands back its index when
+// it's one of the len options listed
+fn pick(len: usize) -> Option<usize> {
     let mut selection = String::new();
 
     read_input(&mut selection);
 
-    let index = selection.trim().parse::<usize>();
-
-    match index {
+    match selection.trim().parse::<usize>() {
+        Ok(i) if i >= 1 && i <= len => Some(i - 1),
         Ok(i) => {
-            let bird = &birds[i - 1];
-            println!();
-            println!("{} ({})", bird.name, bird.scientific_name);
-            println!("{}", bird.blurb);
-            println!("To learn more visit {}", bird.link);
+            println!("{} isn't one of the options listed", i);
+            None
+        }
+        Err(err) => {
+            println!("couldnt parse index: {:?}", err);
+            None
         }
-        Err(err) => println!("couldnt parse index: {:?}", err),
     }
 }
 
-fn read_input(s: &mut String) {
-    io::stdin().read_line(s).expect("Failed to read line");
+fn exit_with(err: birdme::BirdError) -> ! {
+    println!("{}", err);
+    std::process::exit(1);
 }
 
-fn fetch_birds(region: &str) -> Vec<Bird> {
-    vec![
-        Bird {
-            name: String::from("American Robin"),
-            scientific_name: String::from("Turdus migratorius"),
-            link: String::from("https://en.wikipedia.org/wiki/American_robin"),
-            blurb: String::from("Eats a ton of worms every day..."),
-        },
-        Bird {
-            name: String::from("Barn Swallow"),
-            scientific_name: String::from("Hirundo rustica"),
-            link: String::from("https://en.wikipedia.org/wiki/Barn_swallow"),
-            blurb: String::from("Likes to nest under bridges..."),
-        },
-        Bird {
-            name: String::from("Barred Owl"),
-            scientific_name: String::from("Strix varia"),
-            link: String::from("https://en.wikipedia.org/wiki/Barred_owl"),
-            blurb: String::from("Very majestic in the winter..."),
-        },
-        Bird {
-            name: String::from("Blue Jay"),
-            scientific_name: String::from("Cyanocitta cristata"),
-            link: String::from("https://en.wikipedia.org/wiki/Blue_jay"),
-            blurb: String::from("Loud and rude to others..."),
-        },
-        Bird {
-            name: String::from("Common Raven"),
-            scientific_name: String::from("Corvus corax"),
-            link: String::from("https://en.wikipedia.org/wiki/Common_raven"),
-            blurb: String::from("Found across America..."),
-        },
-    ]
+fn read_input(s: &mut String) {
+    io::stdin().read_line(s).expect("Failed to read line");
 }
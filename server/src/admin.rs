@@ -0,0 +1,37 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use subtle::ConstantTimeEq;
+
+use crate::config::ServiceConfig;
+use crate::error::ApiError;
+
+// header the admin token is sent in
+const ADMIN_HEADER: &str = "X-Admin-Token";
+
+// Admin is a request guard for routes that change server state. It only lets
+// a request through when the X-Admin-Token header matches the ADMIN_TOKEN the
+// server was started with
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let expected = req
+            .rocket()
+            .state::<ServiceConfig>()
+            .and_then(|c| c.admin_token.as_deref());
+
+        match (expected, req.headers().get_one(ADMIN_HEADER)) {
+            // compared in constant time so the token can't be guessed a byte
+            // at a time from how long a wrong one takes to turn down
+            (Some(expected), Some(given))
+                if bool::from(expected.as_bytes().ct_eq(given.as_bytes())) =>
+            {
+                Outcome::Success(Admin)
+            }
+            _ => Outcome::Error((Status::Unauthorized, ApiError::Unauthorized)),
+        }
+    }
+}
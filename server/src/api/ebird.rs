@@ -1,153 +1,1366 @@
-use rand::Rng;
+use birdme_types::SearchResult;
+use chrono::{Datelike, Duration, Months, NaiveDate, Utc};
+use futures::stream::{self, StreamExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
 
 const BASE_URL: &str = "https://api.ebird.org/v2/";
 const KEY_HEADER: &str = "x-ebirdapitoken";
 
+use crate::cache::Cache;
+use crate::lang::DEFAULT_LANG;
+use crate::provider::{to_search_result, ProviderError, SpeciesProvider};
+use crate::taxonomy::{normalize, Taxon, TaxonomyIndex};
+use tokio::sync::Mutex;
+
+// cache key prefixes, also what the admin route takes to clear one kind
+pub const SPECIES_LIST_KEY: &str = "spplist:";
+pub const TAXONOMY_KEY: &str = "taxonomy:";
+// the whole downloaded taxonomy, under the taxonomy prefix so clearing that
+// takes it along. No species code has a * in it
+const FULL_TAXONOMY_KEY: &str = "taxonomy:*";
+pub const HISTORIC_KEY: &str = "historic:";
+pub const NAMES_KEY: &str = "names:";
+
+// the year is split up the way eBird's bar charts do it, four "weeks" a month
+// with the last one running to the end of the month
+pub const WEEKS_PER_YEAR: usize = 48;
+// how many historic days are fetched at once
+const HISTORIC_CONCURRENCY: usize = 8;
+// days older than this have had their checklists come in, so what was seen on
+// them can be cached for good
+const SETTLED_DAYS: i64 = 30;
+
 pub struct EbirdService {
     pub token: String,
     client: reqwest::Client,
+    // species lists and taxonomy barely ever change, so they're kept here
+    // instead of being downloaded on every request
+    cache: Cache,
+    // taxonomy loaded from a local file at startup, checked before the cache.
+    // Without a file it's read from the cache or downloaded from eBird the
+    // first time something needs the whole thing, like resolving a name, and
+    // forgotten again when the cache is cleared
+    taxonomy: RwLock<Option<Arc<TaxonomyIndex>>>,
+    // whether taxonomy came from TAXONOMY_FILE, which clearing the cache
+    // leaves alone
+    taxonomy_file: bool,
+    // held while loading the taxonomy so only one request downloads it
+    taxonomy_load: Mutex<()>,
+    // never talk to eBird, answering only from the taxonomy and cache
+    offline: bool,
+}
+
+pub struct Bird {
+    pub name: String,
+    pub scientific_name: String,
+    pub species_code: String,
+    pub taxon_order: f32,
+    pub order: String,
+    pub family_name: String,
+    pub family_scientific_name: String,
+    pub category: String,
+    pub banding_codes: Vec<String>,
+}
+
+impl From<&Taxon> for Bird {
+    fn from(tax: &Taxon) -> Self {
+        Self {
+            name: tax.com_name.clone(),
+            scientific_name: tax.sci_name.clone(),
+            species_code: tax.species_code.clone(),
+            taxon_order: tax.taxon_order,
+            order: tax.order.clone(),
+            family_name: tax.family_com_name.clone(),
+            family_scientific_name: tax.family_sci_name.clone(),
+            category: tax.category.clone(),
+            banding_codes: tax.banding_codes.clone(),
+        }
+    }
 }
 
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
-struct TaxonomyResponse {
-    sci_name: String,
-    com_name: String,
+struct ObservationResponse {
     species_code: String,
-    category: String,
-    taxon_order: f32,
-    banding_codes: Vec<String>,
-    com_name_codes: Vec<String>,
-    sci_name_codes: Vec<String>,
-    order: String,
-    family_code: String,
-    family_com_name: String,
-    family_sci_name: String,
+    com_name: String,
+    sci_name: String,
+    loc_name: String,
+    obs_dt: String,
+    how_many: Option<u32>,
+    lat: f64,
+    lng: f64,
+    obs_valid: bool,
+    obs_reviewed: bool,
 }
 
-pub struct Bird {
+// Frequency is how often a species turned up in a region through the year
+pub struct Frequency {
+    // the years the days were sampled from, oldest first
+    pub years: Vec<i32>,
+    pub samples_per_week: u32,
+    // share of the sampled days in each week the species was reported on
+    pub weeks: Vec<f32>,
+}
+
+// FrequencySampling is how many days go into each week of a frequency chart.
+// Every day is a request the first time a region is asked about, after that
+// they're shared by every species from the cache. A week's share only moves
+// in steps of one over samples_per_week * years, so fewer days make for a
+// noisier chart
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrequencySampling {
+    // days looked at in each week of a year, up to 7
+    pub samples_per_week: u32,
+    // how many of the last full years are looked at, up to 5
+    pub years: u32,
+}
+
+impl FrequencySampling {
+    pub fn new(samples_per_week: u32, years: u32) -> Self {
+        Self {
+            samples_per_week: samples_per_week.clamp(1, 7),
+            years: years.clamp(1, 5),
+        }
+    }
+
+    // days_per_week is how many sampled days each week's share is out of
+    pub fn days_per_week(&self) -> u32 {
+        self.samples_per_week * self.years
+    }
+}
+
+impl Default for FrequencySampling {
+    fn default() -> Self {
+        Self::new(2, 3)
+    }
+}
+
+pub struct Observation {
+    pub species_code: String,
     pub name: String,
-    pub family_name: String,
     pub scientific_name: String,
+    pub count: Option<u32>,
+    pub location: String,
+    pub lat: f64,
+    pub lng: f64,
+    pub date: String,
+    pub valid: bool,
+    pub reviewed: bool,
+}
+
+impl From<ObservationResponse> for Observation {
+    fn from(obs: ObservationResponse) -> Self {
+        Self {
+            species_code: obs.species_code,
+            name: obs.com_name,
+            scientific_name: obs.sci_name,
+            count: obs.how_many,
+            location: obs.loc_name,
+            lat: obs.lat,
+            lng: obs.lng,
+            date: obs.obs_dt,
+            valid: obs.obs_valid,
+            reviewed: obs.obs_reviewed,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HotspotResponse {
+    loc_id: String,
+    loc_name: String,
+    country_code: String,
+    subnational1_code: String,
+    lat: f64,
+    lng: f64,
+    latest_obs_dt: Option<String>,
+    num_species_all_time: Option<u32>,
+}
+
+pub struct Hotspot {
+    pub id: String,
+    pub name: String,
+    pub country: String,
+    pub subnational1: String,
+    pub lat: f64,
+    pub lng: f64,
+    pub latest_observation: Option<String>,
+    pub species_count: Option<u32>,
+}
+
+impl From<HotspotResponse> for Hotspot {
+    fn from(spot: HotspotResponse) -> Self {
+        Self {
+            id: spot.loc_id,
+            name: spot.loc_name,
+            country: spot.country_code,
+            subnational1: spot.subnational1_code,
+            lat: spot.lat,
+            lng: spot.lng,
+            latest_observation: spot.latest_obs_dt,
+            species_count: spot.num_species_all_time,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HotspotInfoResponse {
+    loc_id: String,
+    name: String,
+    country_code: String,
+    subnational1_code: String,
+    subnational2_code: Option<String>,
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(Deserialize)]
+struct RegionResponse {
+    code: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct RegionInfoResponse {
+    // full name of the region including its parents, e.g.
+    // "New York, United States"
+    result: String,
+}
+
+pub struct Region {
+    pub code: String,
+    pub name: String,
+}
+
+// RegionType is the level of region eBird can list below a parent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegionType {
+    Country,
+    Subnational1,
+    Subnational2,
+}
+
+impl RegionType {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "country" => Some(RegionType::Country),
+            "subnational1" => Some(RegionType::Subnational1),
+            "subnational2" => Some(RegionType::Subnational2),
+            _ => None,
+        }
+    }
+
+    // below picks the level directly under a parent region: countries under
+    // the world, states under a country and counties under a state
+    pub fn below(parent: &str) -> Option<Self> {
+        if parent == WORLD {
+            return Some(RegionType::Country);
+        }
+
+        match parent.split('-').count() {
+            1 => Some(RegionType::Subnational1),
+            2 => Some(RegionType::Subnational2),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            RegionType::Country => "country",
+            RegionType::Subnational1 => "subnational1",
+            RegionType::Subnational2 => "subnational2",
+        }
+    }
+}
+
+// parent region used to list every country
+pub const WORLD: &str = "world";
+
+// BirdFilter shapes the random picks handed out by get_birds
+pub struct BirdFilter {
+    // how many birds to pick
+    pub count: usize,
+    // matches any part of the family's common or scientific name, or its
+    // code, e.g. "warbler", "parulidae" or "parule1"
+    pub family: Option<String>,
+    // eBird category to keep, e.g. "species" to leave out spuhs and hybrids
+    pub category: Option<String>,
+    // picks the same birds every time for the same seed. With rarity set
+    // that only holds while the recent sightings stay the same
+    pub seed: Option<u64>,
+    // weighs picks by recent sightings when set, from 0 favouring common
+    // birds, through 0.5 treating everything seen lately the same, to 1
+    // favouring uncommon ones. Birds not seen lately stay unlikely either way
+    pub rarity: Option<f64>,
+}
+
+// the categories eBird sorts its taxonomy into
+pub const CATEGORIES: [&str; 8] = [
+    "species",
+    "issf",
+    "spuh",
+    "slash",
+    "hybrid",
+    "intergrade",
+    "domestic",
+    "form",
+];
+
+impl BirdFilter {
+    // matches is true when the taxon passes both the family and category
+    pub fn matches(&self, taxon: &Taxon) -> bool {
+        if let Some(category) = &self.category {
+            if !taxon.category.eq_ignore_ascii_case(category) {
+                return false;
+            }
+        }
+
+        match &self.family {
+            Some(family) => {
+                let needle = normalize(family);
+                normalize(&taxon.family_com_name).contains(&needle)
+                    || normalize(&taxon.family_sci_name).contains(&needle)
+                    || taxon.family_code.eq_ignore_ascii_case(family)
+            }
+            None => true,
+        }
+    }
+}
+
+impl Default for BirdFilter {
+    fn default() -> Self {
+        Self {
+            count: 5,
+            family: None,
+            category: None,
+            seed: None,
+            rarity: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum EbirdError {
+    // the request never made it to eBird or the connection dropped
+    Transport(String),
+    // eBird answered with a non-success status code
+    Status(u16),
+    // eBird answered with something we couldn't parse
+    Decode(String),
+    // the request went through but there was nothing in it
+    Empty,
+    // the service is running offline and the answer wasn't available locally
+    Offline,
+}
+
+impl fmt::Display for EbirdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EbirdError::Transport(e) => write!(f, "unable to reach eBird: {}", e),
+            EbirdError::Status(s) => write!(f, "eBird responded with status {}", s),
+            EbirdError::Decode(e) => write!(f, "unable to parse the eBird response: {}", e),
+            EbirdError::Empty => write!(f, "eBird returned no results"),
+            EbirdError::Offline => {
+                write!(f, "running offline and the data isn't available locally")
+            }
+        }
+    }
 }
 
 impl EbirdService {
-    pub fn new(token: String) -> Self {
+    pub fn new(token: String, cache: Cache, taxonomy: Arc<TaxonomyIndex>, offline: bool) -> Self {
         Self {
             token,
             client: reqwest::Client::new(),
+            cache,
+            taxonomy_file: !taxonomy.is_empty(),
+            taxonomy: RwLock::new(match taxonomy.is_empty() {
+                true => None,
+                false => Some(taxonomy),
+            }),
+            taxonomy_load: Mutex::new(()),
+            offline,
         }
     }
 
-    pub async fn get_birds(&self, region: &str) -> Vec<Bird> {
-        let species_codes = self.get_species_codes_for_region(region).await;
+    // full_taxonomy hands back the whole taxonomy index. Without a local file
+    // it comes out of the cache, or is downloaded from eBird and cached when
+    // it isn't there
+    pub async fn full_taxonomy(&self) -> Result<Arc<TaxonomyIndex>, EbirdError> {
+        if let Some(index) = self.loaded_taxonomy() {
+            return Ok(index);
+        }
 
-        // choose a few random species to return to the user
-        let codes = choose_random_codes(&species_codes, 5);
+        let _loading = self.taxonomy_load.lock().await;
+        // someone else may have loaded it while we waited
+        if let Some(index) = self.loaded_taxonomy() {
+            return Ok(index);
+        }
+
+        let taxa: Vec<Taxon> = match self.cache.get(FULL_TAXONOMY_KEY) {
+            Some(taxa) => taxa,
+            None => {
+                let taxa: Vec<Taxon> = self
+                    .fetch("ref/taxonomy/ebird", &[("fmt", "json".to_owned())])
+                    .await?;
+                println!("Downloaded {} taxa from eBird", taxa.len());
+                self.cache.insert(FULL_TAXONOMY_KEY, &taxa);
+                taxa
+            }
+        };
+
+        let index = Arc::new(TaxonomyIndex::new(taxa));
+        *self.taxonomy.write().expect("locking the taxonomy") = Some(index.clone());
+
+        Ok(index)
+    }
+
+    // loaded_taxonomy hands back the taxonomy if it's already in memory
+    fn loaded_taxonomy(&self) -> Option<Arc<TaxonomyIndex>> {
+        self.taxonomy.read().expect("locking the taxonomy").clone()
+    }
+
+    // invalidate_cache forgets cached species lists and taxonomy whose keys
+    // start with prefix, or all of them without one. Clearing the whole
+    // taxonomy also drops the copy in memory, so the next request picks up
+    // eBird's latest
+    pub fn invalidate_cache(&self, prefix: Option<&str>) -> usize {
+        let prefix = prefix.unwrap_or("");
+        if !self.taxonomy_file && FULL_TAXONOMY_KEY.starts_with(prefix) {
+            *self.taxonomy.write().expect("locking the taxonomy") = None;
+        }
+
+        self.cache.invalidate(Some(prefix))
+    }
+
+    // recent_shares maps each species reported in the region over the last
+    // month to the share of sampled days it was reported on. Going by days
+    // rather than flock sizes keeps one big flock of starlings from counting
+    // for more than a bird someone reports every day
+    async fn recent_shares(&self, region: &str) -> Result<HashMap<String, f64>, EbirdError> {
+        let dates = recent_sample_dates(Utc::now().date_naive());
+
+        let days: Vec<Result<Vec<String>, EbirdError>> = stream::iter(dates.clone())
+            .map(|date| self.historic_species(region, date))
+            .buffer_unordered(HISTORIC_CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut seen: HashMap<String, u32> = HashMap::new();
+        for day in days {
+            for code in day? {
+                *seen.entry(code).or_default() += 1;
+            }
+        }
+
+        Ok(seen
+            .into_iter()
+            .map(|(code, n)| (code, f64::from(n) / dates.len() as f64))
+            .collect())
+    }
 
-        self.get_taxonomy_for_codes(&codes).await
+    // recent_observations lists the latest sighting of each species reported
+    // in the region over the last back_days days (eBird defaults to 14)
+    pub async fn recent_observations(
+        &self,
+        region: &str,
+        back_days: Option<u8>,
+        max_results: Option<u32>,
+        lang: &str,
+    ) -> Result<Vec<Observation>, EbirdError> {
+        self.observations(
+            &format!("data/obs/{}/recent", region),
+            locale_query(lang),
+            back_days,
+            max_results,
+        )
+        .await
     }
 
-    // for some reason, the species codes are returned as plain text from the API
-    // so this method does some string stuff to clean up the result we get back
-    async fn get_species_codes_for_region(&self, region: &str) -> Vec<String> {
-        let v = match self
+    // notable_observations lists recent sightings of species that are rare
+    // for the region or were seen somewhere unusual
+    pub async fn notable_observations(
+        &self,
+        region: &str,
+        back_days: Option<u8>,
+        max_results: Option<u32>,
+        lang: &str,
+    ) -> Result<Vec<Observation>, EbirdError> {
+        self.observations(
+            &format!("data/obs/{}/recent/notable", region),
+            locale_query(lang),
+            back_days,
+            max_results,
+        )
+        .await
+    }
+
+    // species_observations lists the latest sightings of one species at each
+    // location in the region over the last back_days days
+    pub async fn species_observations(
+        &self,
+        region: &str,
+        species_code: &str,
+        back_days: Option<u8>,
+    ) -> Result<Vec<Observation>, EbirdError> {
+        self.observations(
+            &format!("data/obs/{}/recent/{}", region, species_code),
+            vec![],
+            back_days,
+            None,
+        )
+        .await
+    }
+
+    // frequency samples a few days out of every week of the last few years and
+    // counts how many of them the species was reported on in the region. It's
+    // a rough cut of eBird's bar charts, which go by checklists instead of days
+    pub async fn frequency(
+        &self,
+        region: &str,
+        species_code: &str,
+        sampling: FrequencySampling,
+    ) -> Result<Frequency, EbirdError> {
+        // only finished years, so every day in them is cached for good, see
+        // historic_species
+        let last = Utc::now().year() - 1;
+        let years: Vec<i32> = (last + 1 - sampling.years as i32..=last).collect();
+
+        let dates: Vec<(usize, NaiveDate)> = years
+            .iter()
+            .flat_map(|&year| week_sample_dates(year, sampling.samples_per_week))
+            .collect();
+
+        let days: Vec<Result<(usize, bool), EbirdError>> = stream::iter(dates)
+            .map(|(week, date)| async move {
+                let codes = self.historic_species(region, date).await?;
+                Ok((week, codes.iter().any(|c| c == species_code)))
+            })
+            .buffer_unordered(HISTORIC_CONCURRENCY)
+            .collect()
+            .await;
+
+        let mut seen = vec![0; WEEKS_PER_YEAR];
+        for day in days {
+            let (week, found) = day?;
+            if found {
+                seen[week] += 1;
+            }
+        }
+
+        let out_of = sampling.days_per_week() as f32;
+        Ok(Frequency {
+            years,
+            samples_per_week: sampling.samples_per_week,
+            weeks: seen.into_iter().map(|n: u32| n as f32 / out_of).collect(),
+        })
+    }
+
+    // nearby_observations lists recent sightings within dist_km kilometers of
+    // the given point (eBird caps this at 50km)
+    pub async fn nearby_observations(
+        &self,
+        lat: f64,
+        lng: f64,
+        dist_km: Option<u16>,
+        back_days: Option<u8>,
+        max_results: Option<u32>,
+        lang: &str,
+    ) -> Result<Vec<Observation>, EbirdError> {
+        let mut query = geo_query(lat, lng, dist_km);
+        query.extend(locale_query(lang));
+
+        self.observations("data/obs/geo/recent", query, back_days, max_results)
+            .await
+    }
+
+    // nearby_hotspots lists the hotspots within dist_km kilometers of the given
+    // point (eBird caps this at 500km)
+    pub async fn nearby_hotspots(
+        &self,
+        lat: f64,
+        lng: f64,
+        dist_km: Option<u16>,
+    ) -> Result<Vec<Hotspot>, EbirdError> {
+        let mut query = geo_query(lat, lng, dist_km);
+        // hotspots default to CSV
+        query.push(("fmt", "json".to_owned()));
+
+        let spots: Vec<HotspotResponse> = self.fetch("ref/hotspot/geo", &query).await?;
+
+        Ok(spots.into_iter().map(Hotspot::from).collect())
+    }
+
+    // region_hotspots lists the hotspots in a region, limited to ones with
+    // sightings in the last back_days days when given
+    pub async fn region_hotspots(
+        &self,
+        region: &str,
+        back_days: Option<u8>,
+    ) -> Result<Vec<Hotspot>, EbirdError> {
+        let mut query = vec![("fmt", "json".to_owned())];
+        if let Some(back) = back_days {
+            query.push(("back", back.to_string()));
+        }
+
+        let spots: Vec<HotspotResponse> = self
+            .fetch(&format!("ref/hotspot/{}", region), &query)
+            .await?;
+
+        Ok(spots.into_iter().map(Hotspot::from).collect())
+    }
+
+    // hotspot_info looks up a single hotspot. eBird's info endpoint leaves out
+    // the species count and latest sighting, so those are filled in from the
+    // hotspot list of the county (or state) it sits in
+    pub async fn hotspot_info(&self, loc_id: &str) -> Result<Hotspot, EbirdError> {
+        let info: HotspotInfoResponse = self
+            .fetch(&format!("ref/hotspot/info/{}", loc_id), &[])
+            .await?;
+
+        let region = info
+            .subnational2_code
+            .as_deref()
+            .unwrap_or(&info.subnational1_code);
+        let stats = self
+            .region_hotspots(region, None)
+            .await?
+            .into_iter()
+            .find(|spot| spot.id == info.loc_id);
+
+        Ok(Hotspot {
+            latest_observation: stats.as_ref().and_then(|s| s.latest_observation.clone()),
+            species_count: stats.and_then(|s| s.species_count),
+            id: info.loc_id,
+            name: info.name,
+            country: info.country_code,
+            subnational1: info.subnational1_code,
+            lat: info.latitude,
+            lng: info.longitude,
+        })
+    }
+
+    // regions lists the regions of the given type inside a parent region,
+    // e.g. the subnational1 regions (states) of US
+    pub async fn regions(
+        &self,
+        region_type: RegionType,
+        parent: &str,
+    ) -> Result<Vec<Region>, EbirdError> {
+        let regions: Vec<RegionResponse> = self
+            .fetch(
+                &format!("ref/region/list/{}/{}", region_type.as_str(), parent),
+                &[],
+            )
+            .await?;
+
+        Ok(regions
+            .into_iter()
+            .map(|r| Region {
+                code: r.code,
+                name: r.name,
+            })
+            .collect())
+    }
+
+    // region_info looks up the full name of a region code
+    pub async fn region_info(&self, code: &str) -> Result<Region, EbirdError> {
+        let info: RegionInfoResponse = self
+            .fetch(&format!("ref/region/info/{}", code), &[])
+            .await?;
+
+        Ok(Region {
+            code: code.to_owned(),
+            name: info.result,
+        })
+    }
+
+    async fn observations(
+        &self,
+        path: &str,
+        mut query: Vec<(&str, String)>,
+        back_days: Option<u8>,
+        max_results: Option<u32>,
+    ) -> Result<Vec<Observation>, EbirdError> {
+        if let Some(back) = back_days {
+            query.push(("back", back.to_string()));
+        }
+        if let Some(max) = max_results {
+            query.push(("maxResults", max.to_string()));
+        }
+
+        let obs: Vec<ObservationResponse> = self.fetch(path, &query).await?;
+
+        Ok(obs.into_iter().map(Observation::from).collect())
+    }
+
+    // historic_species lists the species reported in the region on a past
+    // date. Once a day has settled it doesn't change, so even an empty one is
+    // kept past the ttl
+    async fn historic_species(
+        &self,
+        region: &str,
+        date: NaiveDate,
+    ) -> Result<Vec<String>, EbirdError> {
+        let key = format!("{}{}:{}", HISTORIC_KEY, region, date.format("%Y-%m-%d"));
+        if let Some(codes) = self.cache.get(&key) {
+            return Ok(codes);
+        }
+
+        let path = format!("data/obs/{}/historic/{}", region, date.format("%Y/%-m/%-d"));
+        let obs: Vec<ObservationResponse> =
+            self.fetch(&path, &[("cat", "species".to_owned())]).await?;
+
+        let codes: Vec<String> = obs.into_iter().map(|o| o.species_code).collect();
+        match Utc::now().date_naive() - date > Duration::days(SETTLED_DAYS) {
+            true => self.cache.insert_permanent(&key, &codes),
+            false => self.cache.insert(&key, &codes),
+        }
+
+        Ok(codes)
+    }
+
+    // the species list comes back as a plain text content type, but the body
+    // is still a JSON array of species codes
+    async fn get_species_codes_for_region(&self, region: &str) -> Result<Vec<String>, EbirdError> {
+        let key = format!("{}{}", SPECIES_LIST_KEY, region);
+        if let Some(codes) = self.cache.get(&key) {
+            return Ok(codes);
+        }
+
+        // offline demos don't know which birds live where, so any species in
+        // the local taxonomy will do
+        if let (true, Some(index)) = (self.offline, self.loaded_taxonomy()) {
+            return Ok(index
+                .iter()
+                .filter(|t| t.category == "species")
+                .map(|t| t.species_code.clone())
+                .collect());
+        }
+
+        let codes: Vec<String> = self
+            .fetch(&format!("product/spplist/{}", region), &[])
+            .await?;
+        // an empty list is more likely a hiccup than a region without birds,
+        // so don't hold on to it
+        if !codes.is_empty() {
+            self.cache.insert(&key, &codes);
+        }
+
+        Ok(codes)
+    }
+
+    // localized_names maps codes to their common names in lang, from the cache
+    // where possible. The local taxonomy is English only
+    async fn localized_names(
+        &self,
+        species_codes: &[String],
+        lang: &str,
+    ) -> Result<HashMap<String, String>, EbirdError> {
+        let key = |code: &str| format!("{}{}:{}", NAMES_KEY, lang, code);
+
+        let mut names = HashMap::new();
+        let mut missing = vec![];
+        for code in species_codes {
+            match self.cache.get::<String>(&key(code)) {
+                Some(name) => {
+                    names.insert(code.clone(), name);
+                }
+                None => missing.push(code.clone()),
+            }
+        }
+
+        if !missing.is_empty() {
+            let taxa: Vec<Taxon> = self
+                .fetch(
+                    "ref/taxonomy/ebird",
+                    &[
+                        ("species", missing.join(",")),
+                        ("fmt", "json".to_owned()),
+                        ("locale", lang.to_owned()),
+                    ],
+                )
+                .await?;
+
+            for tax in taxa {
+                self.cache.insert(&key(&tax.species_code), &tax.com_name);
+                names.insert(tax.species_code, tax.com_name);
+            }
+        }
+
+        Ok(names)
+    }
+
+    // get_taxonomy_for_codes resolves each code from the local taxonomy, then
+    // the cache, and only asks eBird for whatever is left
+    async fn get_taxonomy_for_codes(
+        &self,
+        species_codes: &[String],
+    ) -> Result<Vec<Bird>, EbirdError> {
+        let index = self.loaded_taxonomy();
+        let mut found: HashMap<String, Taxon> = HashMap::new();
+        let mut missing = vec![];
+        for code in species_codes {
+            if let Some(tax) = index.as_ref().and_then(|t| t.by_code(code)) {
+                found.insert(code.clone(), tax.clone());
+                continue;
+            }
+
+            match self.cache.get(&format!("{}{}", TAXONOMY_KEY, code)) {
+                Some(tax) => {
+                    found.insert(code.clone(), tax);
+                }
+                None => missing.push(code.clone()),
+            }
+        }
+
+        if !missing.is_empty() {
+            let taxes: Vec<Taxon> = self
+                .fetch(
+                    "ref/taxonomy/ebird",
+                    &[("species", missing.join(",")), ("fmt", "json".to_owned())],
+                )
+                .await?;
+
+            for tax in taxes {
+                self.cache
+                    .insert(&format!("{}{}", TAXONOMY_KEY, tax.species_code), &tax);
+                found.insert(tax.species_code.clone(), tax);
+            }
+        }
+
+        // keep the order the codes were asked for in
+        let birds: Vec<Bird> = species_codes
+            .iter()
+            .filter_map(|code| found.get(code))
+            .map(Bird::from)
+            .collect();
+        if birds.is_empty() {
+            return Err(EbirdError::Empty);
+        }
+
+        Ok(birds)
+    }
+
+    // fetch sends an authenticated GET for the given API path and decodes the
+    // JSON body, sorting failures into the ways eBird can let us down
+    async fn fetch<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, EbirdError> {
+        if self.offline {
+            return Err(EbirdError::Offline);
+        }
+
+        let res = self
             .client
-            .get(format!("{}product/spplist/{}", BASE_URL, region))
+            .get(format!("{}{}", BASE_URL, path))
             .header(KEY_HEADER, &self.token)
+            .query(query)
             .send()
             .await
-        {
-            Ok(res) => {
-                // parse as text and clean up the response
-                if let Ok(r) = res.text().await {
-                    let v = r[1..r.len() - 2]
-                        .replace("\"", "")
-                        .split(",")
-                        .map(|code| String::from(code))
+            .map_err(|e| EbirdError::Transport(e.to_string()))?;
+
+        let status = res.status();
+        if !status.is_success() {
+            return Err(EbirdError::Status(status.as_u16()));
+        }
+
+        res.json::<T>()
+            .await
+            .map_err(|e| EbirdError::Decode(e.to_string()))
+    }
+}
+
+#[rocket::async_trait]
+impl SpeciesProvider for EbirdService {
+    // random_birds picks filter.count random species from the region's species
+    // list, narrowed down by family and category when the filter asks for it
+    async fn random_birds(
+        &self,
+        region: &str,
+        filter: &BirdFilter,
+    ) -> Result<Vec<Bird>, ProviderError> {
+        let mut species_codes = self.get_species_codes_for_region(region).await?;
+
+        // the species list is only codes, so narrowing it down needs the
+        // whole taxonomy
+        if filter.family.is_some() || filter.category.is_some() {
+            let index = self.full_taxonomy().await?;
+            species_codes.retain(|code| index.by_code(code).is_some_and(|t| filter.matches(t)));
+        }
+
+        if species_codes.is_empty() {
+            return Err(ProviderError::NotFound);
+        }
+
+        // the species list comes back (and is cached) in taxonomic order, so
+        // the same seed picks the same birds until the list itself changes
+        let mut rng = match filter.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        let codes = match filter.rarity {
+            Some(rarity) => match self.recent_shares(region).await {
+                Ok(shares) => {
+                    let weights: Vec<f64> = species_codes
+                        .iter()
+                        .map(|c| species_weight(shares.get(c).copied(), rarity))
                         .collect();
-                    v
-                } else {
-                    println!("Unable to parse the species codes as text");
-                    vec![]
+                    choose_weighted_codes(&species_codes, &weights, filter.count, &mut rng)
+                }
+                Err(e) => {
+                    println!(
+                        "Unable to weigh the birds in {}, picking evenly: {}",
+                        region, e
+                    );
+                    choose_random_codes(&species_codes, filter.count, &mut rng)
                 }
+            },
+            None => choose_random_codes(&species_codes, filter.count, &mut rng),
+        };
+
+        Ok(self.get_taxonomy_for_codes(&codes).await?)
+    }
+
+    // bird_of_the_day picks one species for the region and date. The pick is
+    // seeded by the date, so it's the same for everyone
+    async fn bird_of_the_day(
+        &self,
+        region: &str,
+        date: NaiveDate,
+        exclude: &[String],
+    ) -> Result<Bird, ProviderError> {
+        let mut codes = self.get_species_codes_for_region(region).await?;
+        // spuhs and hybrids make for a confusing bird of the day
+        match self.full_taxonomy().await {
+            Ok(index) => {
+                codes.retain(|c| index.by_code(c).is_some_and(|t| t.category == "species"))
             }
-            Err(err) => {
+            Err(e) => println!(
+                "Unable to load the taxonomy, picking from every code: {}",
+                e
+            ),
+        }
+
+        let fresh: Vec<String> = codes
+            .iter()
+            .filter(|c| !exclude.contains(c))
+            .cloned()
+            .collect();
+        // a region with fewer species than days to sit out has to repeat
+        let candidates = if fresh.is_empty() { &codes } else { &fresh };
+
+        let mut rng = StdRng::seed_from_u64(daily_seed(region, date));
+        let picked = choose_random_codes(candidates, 1, &mut rng);
+
+        let mut birds = self.get_taxonomy_for_codes(&picked).await?;
+        birds.pop().ok_or(ProviderError::NotFound)
+    }
+
+    // species looks up a single bird by species code, banding code or name,
+    // allowing for typos in the name
+    async fn species(&self, query: &str) -> Result<Bird, ProviderError> {
+        match self.full_taxonomy().await {
+            Ok(index) => index
+                .resolve(query)
+                .map(Bird::from)
+                .ok_or(ProviderError::NotFound),
+            // without the whole taxonomy the best we can do is treat the
+            // query as a species code
+            Err(e) => {
                 println!(
-                    "Oops we failed fetching the species codes for region {}: {}",
-                    region, err
+                    "Unable to load the taxonomy, looking up {} as a code: {}",
+                    query, e
                 );
-                vec![]
+                let mut birds = self.get_taxonomy_for_codes(&[query.to_owned()]).await?;
+                birds.pop().ok_or(ProviderError::NotFound)
             }
-        };
+        }
+    }
+
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>, ProviderError> {
+        let index = self.full_taxonomy().await?;
 
-        v
+        Ok(index
+            .search(query, limit)
+            .into_iter()
+            .map(to_search_result)
+            .collect())
     }
 
-    async fn get_taxonomy_for_codes(&self, species_codes: &Vec<String>) -> Vec<Bird> {
-        let codes = species_codes.join(",");
+    // localize swaps the birds' common names for their names in lang. Birds
+    // eBird has no translation for keep their English name
+    async fn localize(&self, birds: &mut [Bird], lang: &str) {
+        if lang == DEFAULT_LANG {
+            return;
+        }
 
-        let birds = match self
-            .client
-            .get(format!("{}ref/taxonomy/ebird", BASE_URL))
-            .header(KEY_HEADER, &self.token)
-            .query(&[("species", codes)])
-            .query(&[("fmt", "json")])
-            .send()
-            .await
-            .expect("didn't error grabbing the taxonomy")
-            .json::<Vec<TaxonomyResponse>>()
-            .await
-        {
-            Ok(taxes) => taxes
-                .iter()
-                .map(|tax| Bird {
-                    name: tax.com_name.clone(),
-                    family_name: tax.family_com_name.clone(),
-                    scientific_name: tax.sci_name.clone(),
-                })
-                .collect(),
-            Err(err) => {
-                println!("Oops we failed fetching the taxonomies: {}", err);
-                vec![]
+        let codes: Vec<String> = birds.iter().map(|b| b.species_code.clone()).collect();
+        match self.localized_names(&codes, lang).await {
+            Ok(names) => {
+                for bird in birds {
+                    if let Some(name) = names.get(&bird.species_code) {
+                        bird.name = name.clone();
+                    }
+                }
             }
-        };
+            Err(e) => println!("Unable to get the {} names, keeping English: {}", lang, e),
+        }
+    }
+}
 
-        birds
+fn geo_query(lat: f64, lng: f64, dist_km: Option<u16>) -> Vec<(&'static str, String)> {
+    let mut query = vec![("lat", lat.to_string()), ("lng", lng.to_string())];
+    if let Some(dist) = dist_km {
+        query.push(("dist", dist.to_string()));
     }
+
+    query
 }
 
-// choose_random_codes utilizes a random number generator to snag some random
-// species codes to show the user
-fn choose_random_codes(species_codes: &Vec<String>, number_to_choose: u8) -> Vec<String> {
-    let mut codes: Vec<String> = Vec::new();
-    let mut rng = rand::thread_rng();
-    let mut set = HashSet::new();
+// is_valid_loc_id checks that an id looks like an eBird location, e.g. L109516
+pub fn is_valid_loc_id(loc_id: &str) -> bool {
+    match loc_id.strip_prefix('L') {
+        Some(digits) => !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
 
-    let max_counter = 100;
-    let mut kill_it = false;
+// is_valid_region checks that a region looks like an eBird region code before
+// we spend a request on it: a two letter country code optionally followed by
+// subnational parts, e.g. US, US-NY or US-NY-109
+pub fn is_valid_region(region: &str) -> bool {
+    let parts: Vec<&str> = region.split('-').collect();
+    if parts.len() > 3 {
+        return false;
+    }
 
-    for _ in 0..number_to_choose {
-        let mut i = 0;
-        loop {
-            let random_number = rng.gen_range(0..species_codes.len());
-            if !set.contains(&random_number) {
-                set.insert(random_number);
-                codes.push(String::from(&species_codes[random_number]));
-                break;
-            }
-            i += 1;
-            if i >= max_counter {
-                kill_it = true;
-                break;
+    if parts[0].len() != 2 || !parts[0].chars().all(|c| c.is_ascii_alphabetic()) {
+        return false;
+    }
+
+    parts[1..]
+        .iter()
+        .all(|p| !p.is_empty() && p.len() <= 3 && p.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+// how far back recent sightings go when weighing picks
+const RECENT_WEIGHT_DAYS: i64 = 30;
+// only every so many days are looked at, each one is a request the first time
+// a region is weighed. Days are picked by date rather than counted back from
+// today, so most of them are still cached tomorrow
+const RECENT_SAMPLE_EVERY: i32 = 3;
+
+// weight given to species on the region's list that haven't been reported
+// lately, i.e. out of season or vagrants that showed up once years ago. Kept
+// under the share of a species seen on just one sampled day
+const UNSEEN_WEIGHT: f64 = 0.05;
+
+// species_weight rates how likely a species should be to get picked given the
+// share of recent days it was reported on, if any, bent toward common or
+// uncommon birds by rarity
+fn species_weight(share: Option<f64>, rarity: f64) -> f64 {
+    match share {
+        Some(s) if s > 0.0 => s.powf(1.0 - 2.0 * rarity),
+        _ => UNSEEN_WEIGHT,
+    }
+}
+
+// recent_sample_dates lists the days looked at when weighing picks on today.
+// Yesterday is left out as plenty of checklists from it are still to come in
+fn recent_sample_dates(today: NaiveDate) -> Vec<NaiveDate> {
+    (2..=RECENT_WEIGHT_DAYS)
+        .map(|back| today - Duration::days(back))
+        .filter(|d| d.num_days_from_ce() % RECENT_SAMPLE_EVERY == 0)
+        .collect()
+}
+
+// choose_weighted_codes picks up to number_to_choose distinct codes, each
+// code's chance following its weight. weights lines up with species_codes
+fn choose_weighted_codes<R: Rng>(
+    species_codes: &[String],
+    weights: &[f64],
+    number_to_choose: usize,
+    rng: &mut R,
+) -> Vec<String> {
+    let amount = number_to_choose.min(species_codes.len());
+
+    match rand::seq::index::sample_weighted(rng, species_codes.len(), |i| weights[i], amount) {
+        Ok(picked) => picked
+            .into_iter()
+            .map(|i| species_codes[i].clone())
+            .collect(),
+        // only happens with weights that aren't positive numbers
+        Err(e) => {
+            println!("Unable to pick weighted codes, picking evenly: {}", e);
+            choose_random_codes(species_codes, number_to_choose, rng)
+        }
+    }
+}
+
+// locale_query asks eBird for species names in lang, leaving English to
+// eBird's default
+fn locale_query(lang: &str) -> Vec<(&'static str, String)> {
+    match lang {
+        DEFAULT_LANG => vec![],
+        _ => vec![("sppLocale", lang.to_owned())],
+    }
+}
+
+// week_sample_dates picks samples evenly spaced days out of each of the 48
+// weeks in year, handing each back with the week it belongs to
+fn week_sample_dates(year: i32, samples: u32) -> Vec<(usize, NaiveDate)> {
+    let mut dates = vec![];
+
+    for week in 0..WEEKS_PER_YEAR {
+        let month = (week / 4) as u32 + 1;
+        let first = match NaiveDate::from_ymd_opt(year, month, 1) {
+            Some(d) => d,
+            None => continue,
+        };
+        let days_in_month = first
+            .checked_add_months(Months::new(1))
+            .and_then(|d| d.pred_opt())
+            .map_or(28, |d| d.day());
+
+        let start = 7 * (week as u32 % 4) + 1;
+        let end = match week % 4 {
+            3 => days_in_month,
+            _ => start + 6,
+        };
+        let len = end - start + 1;
+
+        for s in 0..samples {
+            let day = start + len * (2 * s + 1) / (2 * samples);
+            if let Some(date) = first.with_day(day) {
+                dates.push((week, date));
             }
         }
-        if kill_it {
-            break;
+    }
+
+    dates
+}
+
+// daily_seed turns a region and date into a seed. It's FNV-1a rather than
+// the std hasher since that one is free to change between Rust releases,
+// which would reshuffle the birds already handed out
+pub fn daily_seed(region: &str, date: NaiveDate) -> u64 {
+    format!("{}:{}", region, date.format("%Y-%m-%d"))
+        .bytes()
+        .fold(0xcbf29ce484222325, |hash, b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+        })
+}
+
+// choose_random_codes utilizes the given random number generator to snag up
+// to number_to_choose distinct species codes to show the user
+pub fn choose_random_codes<R: Rng>(
+    species_codes: &[String],
+    number_to_choose: usize,
+    rng: &mut R,
+) -> Vec<String> {
+    let amount = number_to_choose.min(species_codes.len());
+
+    rand::seq::index::sample(rng, species_codes.len(), amount)
+        .into_iter()
+        .map(|i| species_codes[i].clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_regions() {
+        assert!(is_valid_region("US"));
+        assert!(is_valid_region("US-NY"));
+        assert!(is_valid_region("US-NY-109"));
+        assert!(is_valid_region("CA-ON"));
+    }
+
+    #[test]
+    fn invalid_regions() {
+        assert!(!is_valid_region(""));
+        assert!(!is_valid_region("USA"));
+        assert!(!is_valid_region("US-"));
+        assert!(!is_valid_region("US-NY-109-1"));
+        assert!(!is_valid_region("US-NEWY"));
+        assert!(!is_valid_region("U1"));
+        assert!(!is_valid_region("US/NY"));
+    }
+
+    #[test]
+    fn region_types() {
+        assert_eq!(RegionType::below(WORLD), Some(RegionType::Country));
+        assert_eq!(RegionType::below("US"), Some(RegionType::Subnational1));
+        assert_eq!(RegionType::below("US-NY"), Some(RegionType::Subnational2));
+        assert_eq!(RegionType::below("US-NY-109"), None);
+    }
+
+    #[test]
+    fn loc_ids() {
+        assert!(is_valid_loc_id("L109516"));
+        assert!(!is_valid_loc_id("L"));
+        assert!(!is_valid_loc_id("109516"));
+        assert!(!is_valid_loc_id("L1095a6"));
+    }
+
+    #[test]
+    fn seeded_choices_repeat() {
+        let codes: Vec<String> = (0..50).map(|i| format!("code{}", i)).collect();
+
+        let first = choose_random_codes(&codes, 5, &mut StdRng::seed_from_u64(42));
+        let second = choose_random_codes(&codes, 5, &mut StdRng::seed_from_u64(42));
+        assert_eq!(first, second);
+        assert_eq!(first.len(), 5);
+
+        let mut unique = first.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), 5);
+
+        // asking for more than there are hands back everything once
+        let all = choose_random_codes(&codes[..3], 5, &mut rand::thread_rng());
+        assert_eq!(all.len(), 3);
+    }
+
+    #[test]
+    fn daily_seeds() {
+        let day = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+
+        // pinned so a change to the seed doesn't silently reshuffle history
+        assert_eq!(daily_seed("US-NY", day), 0x776fa23b97f801ad);
+        assert_ne!(daily_seed("US-NY", day), daily_seed("US-CA", day));
+        assert_ne!(
+            daily_seed("US-NY", day),
+            daily_seed("US-NY", day.succ_opt().unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn taxonomy_from_the_cache() {
+        let cache = Cache::new(10, chrono::Duration::days(1), None);
+        let taxa: Vec<Taxon> = serde_json::from_str(
+            r#"[{"sciName":"Turdus migratorius","comName":"American Robin","speciesCode":"amerob","category":"species","taxonOrder":30419.0}]"#,
+        )
+        .unwrap();
+        cache.insert(FULL_TAXONOMY_KEY, &taxa);
+
+        // offline, so the cache is the only place it can come from
+        let ebird = EbirdService::new(String::new(), cache, Arc::default(), true);
+        let index = ebird.full_taxonomy().await.unwrap();
+        assert!(index.by_code("amerob").is_some());
+
+        // clearing the taxonomy forgets the copy in memory too
+        assert_eq!(ebird.invalidate_cache(Some(TAXONOMY_KEY)), 1);
+        assert!(ebird.full_taxonomy().await.is_err());
+    }
+
+    #[test]
+    fn weights() {
+        // common birds win by default and lose when asking for rarities
+        assert!(species_weight(Some(0.9), 0.0) > species_weight(Some(0.1), 0.0));
+        assert!(species_weight(Some(0.9), 1.0) < species_weight(Some(0.1), 1.0));
+        assert_eq!(
+            species_weight(Some(0.9), 0.5),
+            species_weight(Some(0.1), 0.5)
+        );
+
+        // anything seen lately beats something that hasn't been, even if it
+        // only turned up on one day
+        let once =
+            1.0 / recent_sample_dates(NaiveDate::from_ymd_opt(2024, 5, 1).unwrap()).len() as f64;
+        for rarity in [0.0, 0.5, 1.0] {
+            assert!(species_weight(Some(once), rarity) > species_weight(None, rarity));
+            assert!(species_weight(Some(1.0), rarity) > species_weight(Some(0.0), rarity));
         }
     }
 
-    codes
+    #[test]
+    fn recent_samples() {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let dates = recent_sample_dates(today);
+        assert!((9..=10).contains(&dates.len()));
+        assert!(dates.iter().all(|d| *d < today - Duration::days(1)));
+
+        // tomorrow shares all but a day or so with today
+        let tomorrow = recent_sample_dates(today.succ_opt().unwrap());
+        assert!(tomorrow.iter().filter(|d| dates.contains(d)).count() >= dates.len() - 1);
+    }
+
+    #[test]
+    fn weighted_choices() {
+        let codes: Vec<String> = vec!["common".to_owned(), "rare".to_owned()];
+        let weights = [1000.0, 0.001];
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let common = (0..100)
+            .filter(|_| choose_weighted_codes(&codes, &weights, 1, &mut rng)[0] == "common")
+            .count();
+        assert!(common > 95);
+
+        let both = choose_weighted_codes(&codes, &weights, 5, &mut rng);
+        assert_eq!(both.len(), 2);
+    }
+
+    #[test]
+    fn week_samples() {
+        let dates = week_sample_dates(2024, 2);
+        assert_eq!(dates.len(), WEEKS_PER_YEAR * 2);
+        assert!(dates.iter().all(|(_, d)| d.year() == 2024));
+
+        let day = |m, d| NaiveDate::from_ymd_opt(2024, m, d).unwrap();
+        assert_eq!(dates[0], (0, day(1, 2)));
+        assert_eq!(dates[1], (0, day(1, 6)));
+        // the last week of the month soaks up the leftover days
+        assert_eq!(dates[6], (3, day(1, 24)));
+        assert_eq!(dates[7], (3, day(1, 29)));
+        assert_eq!(dates[15], (7, day(2, 28)));
+        assert_eq!(dates[95], (47, day(12, 29)));
+    }
+
+    #[test]
+    fn frequency_sampling() {
+        let sampling = FrequencySampling::default();
+        assert_eq!(sampling.days_per_week(), 6);
+
+        // every day of the week is as many as there are to sample
+        let sampling = FrequencySampling::new(10, 0);
+        assert_eq!(sampling, FrequencySampling::new(7, 1));
+        assert_eq!(week_sample_dates(2023, 7).len(), WEEKS_PER_YEAR * 7);
+        assert_eq!(FrequencySampling::new(2, 9).years, 5);
+    }
+
+    #[test]
+    fn filters() {
+        let taxon: Taxon = serde_json::from_str(
+            r#"{"sciName":"Setophaga petechia","comName":"Yellow Warbler","speciesCode":"yelwar","category":"species","familyCode":"parule1","familyComName":"New World Warblers","familySciName":"Parulidae"}"#,
+        )
+        .unwrap();
+
+        let filter = |family: Option<&str>, category: Option<&str>| BirdFilter {
+            family: family.map(str::to_owned),
+            category: category.map(str::to_owned),
+            ..Default::default()
+        };
+
+        assert!(filter(None, None).matches(&taxon));
+        assert!(filter(Some("warbler"), None).matches(&taxon));
+        assert!(filter(Some("Parulidae"), Some("species")).matches(&taxon));
+        assert!(filter(Some("parule1"), None).matches(&taxon));
+        assert!(!filter(Some("thrush"), None).matches(&taxon));
+        assert!(!filter(None, Some("hybrid")).matches(&taxon));
+    }
 }
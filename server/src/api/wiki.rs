@@ -1,184 +1,693 @@
-// use chrono::{DateTime, Duration, Utc};
+use birdme_types::html;
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::{self, StreamExt};
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fmt;
+use tokio::sync::Mutex;
+
+use crate::lang::DEFAULT_LANG;
+use crate::provider::{Description, DescriptionProvider, ProviderError};
 
 pub struct WikiService {
     pub client_id: String,
     pub client_secret: String,
 
     client: reqwest::Client,
-    // refresh_token: String,
+    // cached access token, shared by every lookup until it's close to expiring.
+    // The lock is held while refreshing so concurrent requests wait on a
+    // single token request instead of each firing their own
+    token: Mutex<Option<AccessToken>>,
+    // how many lookups get_many runs at once
+    concurrency: usize,
+    // how long a lookup gets before we give up on it
+    timeout: std::time::Duration,
+    // never talk to Wikimedia
+    offline: bool,
 }
 
 pub struct WikiInfo {
     pub title: String,
-    pub snippet: String,
+    // lead section of the article as plain text, paragraphs split by a
+    // blank line
+    pub summary: String,
+    // the same as html, when the summary loaded. Good for rendering with
+    // emphasis and links kept
+    pub summary_html: Option<String>,
+    // short description of the article, e.g. "Species of bird"
+    pub description: Option<String>,
+    // lead image of the article
+    pub thumbnail: Option<Image>,
+    // canonical link to the article
+    pub url: String,
+    // language of the Wikipedia the article came from, which is English when
+    // the language asked for had nothing
+    pub lang: String,
+    // how sure we are this is the bird's article, from 0 to 1
+    pub confidence: f32,
+    // the evidence the article was picked on
+    pub matched_by: Vec<Signal>,
+}
+
+// Signal is a piece of evidence that a Wikipedia article is about the bird
+// we're looking for, rather than a sports team or a Catholic cardinal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Signal {
+    // the article's Wikidata item has the bird's scientific name as its
+    // taxon name (P225)
+    TaxonName,
+    // the article or the redirect the search landed on is titled with the
+    // scientific name
+    ScientificTitle,
+    // the scientific name shows up in the article's text
+    ScientificText,
+    // the article's description mentions birds
+    BirdDescription,
+    // the article is titled with the common name
+    CommonTitle,
+}
+
+impl Signal {
+    fn weight(&self) -> f32 {
+        match self {
+            Signal::TaxonName => 0.6,
+            Signal::ScientificTitle => 0.3,
+            Signal::ScientificText => 0.2,
+            Signal::BirdDescription => 0.2,
+            Signal::CommonTitle => 0.1,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Signal::TaxonName => "taxon_name",
+            Signal::ScientificTitle => "scientific_title",
+            Signal::ScientificText => "scientific_text",
+            Signal::BirdDescription => "bird_description",
+            Signal::CommonTitle => "common_title",
+        }
+    }
 }
 
+// a match this good is taken without looking any further
+const ACCEPT_CONFIDENCE: f32 = 0.5;
+// anything below this has nothing tying it to a bird and is left out
+const MIN_CONFIDENCE: f32 = 0.2;
+// how many of the top search results get their summary and Wikidata checked
+const VERIFY_CANDIDATES: usize = 2;
+
+pub struct Image {
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+// each language has its own Wikipedia, these take the language code
+fn search_endpoint(lang: &str) -> String {
+    format!(
+        "https://api.wikimedia.org/core/v1/wikipedia/{}/search/page",
+        lang
+    )
+}
+
+// page summaries aren't part of the core API, but the REST API they live in
+// doesn't need a token
+fn summary_endpoint(lang: &str) -> String {
+    format!("https://{}.wikipedia.org/api/rest_v1/page/summary/", lang)
+}
+
+// page_url links to the page with key, escaping it so titles with ?, # or %
+// in them still point at the right page
+fn page_url(lang: &str, key: &str) -> String {
+    let mut url = reqwest::Url::parse(&format!("https://{}.wikipedia.org/wiki/", lang))
+        .expect("parsing the page base");
+    url.path_segments_mut()
+        .expect("the page base has a path")
+        .pop_if_empty()
+        .push(key);
+
+    url.to_string()
+}
+const WIKIDATA_ENDPOINT: &str = "https://www.wikidata.org/w/api.php";
+// Wikidata's "taxon name" property
+const TAXON_NAME_PROPERTY: &str = "P225";
+
+// Wikimedia asks every client to say who it is
+const USER_AGENT: &str = "birdme (https://github.com/FlippinBerger/birdme)";
+
 // used for both access tokens and refresh tokens
 const TOKEN_ENDPOINT: &str = "https://meta.wikimedia.org/w/rest.php/oauth2/access_token";
 
+// how long before the token actually expires that we go grab a new one, so a
+// request in flight never goes out with a token that dies on the way
+const REFRESH_MARGIN_SECS: i64 = 60;
+
 #[derive(Deserialize)]
 struct WikiAuthResponse {
     access_token: String,
+    // lifetime of the token in seconds
+    expires_in: i64,
 }
 
-struct Auth {
-    tokens: WikiAuthResponse,
+struct AccessToken {
+    value: String,
+    expires_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone)]
-pub struct WikiError {
-    pub message: String,
-}
+impl AccessToken {
+    fn from_response(res: WikiAuthResponse, now: DateTime<Utc>) -> Self {
+        Self {
+            value: res.access_token,
+            expires_at: now + Duration::seconds(res.expires_in),
+        }
+    }
 
-impl WikiError {
-    fn new(message: String) -> Self {
-        Self { message }
+    // is_fresh is true while the token has more than the refresh margin left
+    fn is_fresh(&self, now: DateTime<Utc>) -> bool {
+        now + Duration::seconds(REFRESH_MARGIN_SECS) < self.expires_at
     }
 }
 
+#[derive(Debug, Clone)]
+pub enum WikiError {
+    // the request never made it to Wikimedia or the connection dropped
+    Transport(String),
+    // Wikimedia answered with a non-success status code
+    Status(u16),
+    // Wikimedia answered with something we couldn't parse
+    Decode(String),
+    // the search went through but nothing matched the name
+    NoResults(String),
+    // the lookup took longer than we were willing to wait
+    Timeout,
+    // the service is running offline
+    Offline,
+}
+
 impl fmt::Display for WikiError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "invalid first item to double")
+        match self {
+            WikiError::Transport(e) => write!(f, "unable to reach Wikimedia: {}", e),
+            WikiError::Status(s) => write!(f, "Wikimedia responded with status {}", s),
+            WikiError::Decode(e) => write!(f, "unable to parse the Wikimedia response: {}", e),
+            WikiError::NoResults(name) => write!(f, "no Wikipedia pages found for {}", name),
+            WikiError::Timeout => write!(f, "Wikimedia took too long to answer"),
+            WikiError::Offline => write!(f, "running offline, Wikipedia isn't available"),
+        }
     }
 }
 
 impl WikiService {
-    pub async fn new(client_id: String, client_secret: String) -> Self {
+    pub async fn new(
+        client_id: String,
+        client_secret: String,
+        concurrency: usize,
+        timeout: std::time::Duration,
+        offline: bool,
+    ) -> Self {
         Self {
             client_id,
             client_secret,
-            client: reqwest::Client::new(),
+            client: reqwest::Client::builder()
+                .user_agent(USER_AGENT)
+                .build()
+                .expect("building the Wikimedia client"),
+            token: Mutex::new(None),
+            // nothing would ever run with no room for lookups
+            concurrency: concurrency.max(1),
+            timeout,
+            offline,
         }
     }
 
-    pub async fn get(&self, name: &str) -> Result<WikiInfo, WikiError> {
-        let auth = Self::auth(&self.client_id, &self.client_secret)
+    // get_many looks up every (name, scientific name) pair, running up to
+    // concurrency lookups at once. Results line up with birds
+    pub async fn get_many(
+        &self,
+        birds: &[(String, String)],
+        lang: &str,
+    ) -> Vec<Result<WikiInfo, WikiError>> {
+        // the lookups are built up front, none of them start until buffered
+        // polls them
+        let lookups: Vec<_> = birds
+            .iter()
+            .map(|(name, scientific_name)| self.get(name, scientific_name, lang))
+            .collect();
+
+        stream::iter(lookups)
+            .buffered(self.concurrency)
+            .collect()
             .await
-            .unwrap();
+    }
+
+    // get looks up the Wikipedia article for a bird in the lang Wikipedia,
+    // giving up after timeout. name should be the bird's name in lang
+    pub async fn get(
+        &self,
+        name: &str,
+        scientific_name: &str,
+        lang: &str,
+    ) -> Result<WikiInfo, WikiError> {
+        tokio::time::timeout(self.timeout, self.search(name, scientific_name, lang))
+            .await
+            .unwrap_or(Err(WikiError::Timeout))
+    }
+
+    // search looks for the bird's article by common name, and by scientific
+    // name too when nothing convincing turns up. Common names like "Cardinal"
+    // or "Kite" are ambiguous, scientific names aren't. Languages other than
+    // English fall back to the English article, found by scientific name
+    // since the common name we have isn't English
+    async fn search(
+        &self,
+        name: &str,
+        scientific_name: &str,
+        lang: &str,
+    ) -> Result<WikiInfo, WikiError> {
+        if self.offline {
+            return Err(WikiError::Offline);
+        }
+
+        // (query, common name, language)
+        let mut attempts = vec![(name, name, lang), (scientific_name, name, lang)];
+        if lang != DEFAULT_LANG {
+            attempts.push((scientific_name, "", DEFAULT_LANG));
+        }
+
+        let mut best: Option<Candidate> = None;
+        let mut last_err = None;
+        for (query, common_name, in_lang) in attempts {
+            if best
+                .as_ref()
+                .is_some_and(|b| b.confidence >= ACCEPT_CONFIDENCE)
+            {
+                break;
+            }
+
+            match self
+                .best_match(query, common_name, scientific_name, in_lang)
+                .await
+            {
+                // ties go to the earlier attempt, so the language asked for
+                // wins over English
+                Ok(Some(c)) => {
+                    if best.as_ref().is_none_or(|b| c.confidence > b.confidence) {
+                        best = Some(c);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    println!(
+                        "Unable to search the {} Wikipedia for {}: {}",
+                        in_lang, query, e
+                    );
+                    last_err = Some(e);
+                }
+            }
+        }
+
+        // only blame Wikimedia when it never gave us anything to go on
+        if best.is_none() {
+            if let Some(e) = last_err {
+                return Err(e);
+            }
+        }
+
+        match best {
+            Some(c) if c.confidence >= MIN_CONFIDENCE => {
+                if c.confidence < ACCEPT_CONFIDENCE {
+                    let signals: Vec<&str> = c.matched_by.iter().map(Signal::as_str).collect();
+                    println!(
+                        "Unsure {} is about {}, confidence {:.2} from {}",
+                        c.page.key,
+                        name,
+                        c.confidence,
+                        signals.join(", ")
+                    );
+                }
+                Ok(c.into_info())
+            }
+            _ => Err(WikiError::NoResults(name.to_owned())),
+        }
+    }
+
+    // best_match searches for query and ranks the results as articles about
+    // the bird, looking closer at the top few until one is convincing
+    async fn best_match(
+        &self,
+        query: &str,
+        name: &str,
+        scientific_name: &str,
+        lang: &str,
+    ) -> Result<Option<Candidate>, WikiError> {
+        let mut candidates: Vec<Candidate> = self
+            .search_pages(query, lang)
+            .await?
+            .into_iter()
+            .map(|page| Candidate::new(page, name, scientific_name, lang))
+            .collect();
+        // stable, so ties keep the search's own ranking
+        candidates.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+
+        let mut best: Option<Candidate> = None;
+        for mut c in candidates.into_iter().take(VERIFY_CANDIDATES) {
+            self.verify(&mut c).await;
+            c.score(name, scientific_name);
+
+            let convincing = c.confidence >= ACCEPT_CONFIDENCE;
+            if best.as_ref().is_none_or(|b| c.confidence > b.confidence) {
+                best = Some(c);
+            }
+            if convincing {
+                break;
+            }
+        }
+
+        Ok(best)
+    }
+
+    async fn search_pages(&self, query: &str, lang: &str) -> Result<Vec<SearchResult>, WikiError> {
+        let token = self.access_token().await?;
 
-        let url = "https://api.wikimedia.org/core/v1/wikipedia/en/search/page"; //q=earth&limit=10
         let res = self
             .client
-            .get(url)
-            .header(
-                "Authorization",
-                "Bearer ".to_owned() + &auth.tokens.access_token,
-            )
-            .query(&[("q", name)])
+            .get(search_endpoint(lang))
+            .header("Authorization", "Bearer ".to_owned() + &token)
+            .query(&[("q", query)])
             .query(&[("limit", "5")])
             .send()
-            .await
-            .expect("error searching wiki")
-            .json::<PagesResult>()
             .await;
 
-        match res {
-            Ok(r) => {
-                // let url = format_html_link(&r.pages[0].title);
-                // let res = self
-                //     .client
-                //     .get(url)
-                //     .header(
-                //         "Authorization",
-                //         "Bearer ".to_owned() + &auth.tokens.access_token,
-                //     )
-                //     // .query(&[("q", name)])
-                //     // .query(&[("limit", "5")])
-                //     .send()
-                //     .await
-                //     .expect("error searching wiki")
-                //     .text()
-                //     .await;
-
-                // if let Ok(s) = res {
-                //     let sanitized = sanitize_snippet(&s);
-                //     fs::write("/tmp/bird.html", s).expect("unable to write to file");
-                //     // let sanitized = sanitize_snippet(&s);
-                //     fs::write("/tmp/sanitized_bird.html", sanitized)
-                //         .expect("Unable to write sanitzied file");
-                // }
-
-                Ok(WikiInfo {
-                    title: r.pages[0].title.clone(),
-                    snippet: sanitize_snippet(&r.pages[0].excerpt.clone()),
-                })
-            }
+        Ok(decode::<PagesResult>(res).await?.pages)
+    }
+
+    // verify fills in the candidate's summary and Wikidata taxon name. Either
+    // one failing only leaves the candidate with less evidence
+    async fn verify(&self, candidate: &mut Candidate) {
+        match self.summary(&candidate.page.key, &candidate.lang).await {
+            Ok(s) => candidate.summary = Some(s),
             Err(e) => {
-                println!("oops we fucked up somewhere searching: {:?}", e);
-                Err(WikiError::new(format!("{:?}", e)))
+                println!("Unable to get the summary of {}: {}", candidate.page.key, e);
+                return;
+            }
+        }
+
+        let item = candidate
+            .summary
+            .as_ref()
+            .and_then(|s| s.wikibase_item.clone());
+        if let Some(item) = item {
+            match self.taxon_name(&item).await {
+                Ok(taxon) => candidate.taxon = taxon,
+                Err(e) => println!("Unable to get the taxon name of {}: {}", item, e),
+            }
+        }
+    }
+
+    // taxon_name looks up the taxon name (P225) of a Wikidata item, which
+    // only taxa have
+    async fn taxon_name(&self, item: &str) -> Result<Option<String>, WikiError> {
+        let res = self
+            .client
+            .get(WIKIDATA_ENDPOINT)
+            .query(&[
+                ("action", "wbgetclaims"),
+                ("entity", item),
+                ("property", TAXON_NAME_PROPERTY),
+                ("format", "json"),
+            ])
+            .send()
+            .await;
+        let r = decode::<ClaimsResponse>(res).await?;
+
+        Ok(r.claims
+            .get(TAXON_NAME_PROPERTY)
+            .and_then(|claims| claims.first())
+            .and_then(|c| c.mainsnak.datavalue.as_ref())
+            .and_then(|v| v.value.as_str())
+            .map(str::to_owned))
+    }
+
+    // summary fetches the lead paragraph and image of the page with key
+    async fn summary(&self, key: &str, lang: &str) -> Result<PageSummary, WikiError> {
+        let mut url =
+            reqwest::Url::parse(&summary_endpoint(lang)).expect("parsing the summary endpoint");
+        url.path_segments_mut()
+            .expect("the summary endpoint has a path")
+            .pop_if_empty()
+            .push(key);
+
+        let res = self.client.get(url).send().await;
+        decode::<PageSummary>(res).await
+    }
+
+    // access_token hands back the cached token, refreshing it first when it's
+    // missing or about to expire
+    async fn access_token(&self) -> Result<String, WikiError> {
+        let mut token = self.token.lock().await;
+
+        if let Some(t) = token.as_ref() {
+            if t.is_fresh(Utc::now()) {
+                return Ok(t.value.clone());
             }
         }
+
+        let fresh = self.auth().await?;
+        let value = fresh.value.clone();
+        *token = Some(fresh);
+
+        Ok(value)
     }
 
-    async fn auth(client_id: &str, client_secret: &str) -> Result<Auth, WikiError> {
+    async fn auth(&self) -> Result<AccessToken, WikiError> {
         let params = [
             ("grant_type", "client_credentials"),
-            ("client_id", client_id),
-            ("client_secret", client_secret),
+            ("client_id", &self.client_id),
+            ("client_secret", &self.client_secret),
         ];
 
-        let client = reqwest::Client::new();
+        let res = self.client.post(TOKEN_ENDPOINT).form(&params).send().await;
+        let r = decode::<WikiAuthResponse>(res).await?;
 
-        let res = client
-            .post(TOKEN_ENDPOINT)
-            .form(&params)
-            .send()
-            .await
-            .expect("Unable to fetch wiki token");
+        Ok(AccessToken::from_response(r, Utc::now()))
+    }
+}
 
-        let new_res = res.json::<WikiAuthResponse>().await;
+#[rocket::async_trait]
+impl DescriptionProvider for WikiService {
+    async fn describe(
+        &self,
+        name: &str,
+        scientific_name: &str,
+        lang: &str,
+    ) -> Result<Description, ProviderError> {
+        Ok(self.get(name, scientific_name, lang).await?.into())
+    }
 
-        match new_res {
-            Ok(r) => Ok(Auth { tokens: r }),
-            Err(e) => {
-                println!("Couldn't get the wiki auth tokens: {}", e);
-                Err(WikiError::new(format!("{:?}", e)))
-            }
+    async fn describe_many(
+        &self,
+        birds: &[(String, String)],
+        lang: &str,
+    ) -> Vec<Result<Description, ProviderError>> {
+        self.get_many(birds, lang)
+            .await
+            .into_iter()
+            .map(|info| Ok(info?.into()))
+            .collect()
+    }
+}
+
+impl From<WikiInfo> for Description {
+    fn from(info: WikiInfo) -> Self {
+        Self {
+            link: Some(info.url),
+            blurb: info.summary,
+            blurb_html: info.summary_html,
+            description: info.description,
+            image: info.thumbnail.map(|t| birdme_types::Image {
+                url: t.url,
+                width: t.width,
+                height: t.height,
+            }),
         }
     }
 }
 
-// fn format_html_link(bird_name: &str) -> String {
-//     let mut name_portion = String::from(bird_name);
-//     name_portion = name_portion.replace(" ", "_");
+// decode turns a sent request into the JSON body it should have come back
+// with, sorting failures into the ways Wikimedia can let us down
+async fn decode<T: DeserializeOwned>(
+    res: Result<reqwest::Response, reqwest::Error>,
+) -> Result<T, WikiError> {
+    let res = res.map_err(|e| WikiError::Transport(e.to_string()))?;
 
-//     let base = "https://api.wikimedia.org/core/v1/wikipedia/en/page/";
+    let status = res.status();
+    if !status.is_success() {
+        return Err(WikiError::Status(status.as_u16()));
+    }
 
-//     format!("{}{}/html", base, name_portion)
-// }
+    res.json::<T>()
+        .await
+        .map_err(|e| WikiError::Decode(e.to_string()))
+}
 
-// sanitize_snippet takes the html tags out of the given snippet
-fn sanitize_snippet(snippet: &str) -> String {
-    let mut clean_snippet = String::new();
+// Candidate is a search result that might be the bird's article, along with
+// whatever else we've found out about it
+struct Candidate {
+    page: SearchResult,
+    // language of the Wikipedia the page is in
+    lang: String,
+    summary: Option<PageSummary>,
+    // Wikidata taxon name, only looked up for the likeliest candidates
+    taxon: Option<String>,
+    confidence: f32,
+    matched_by: Vec<Signal>,
+}
 
-    let mut start = 0;
-    let mut slice = snippet;
+impl Candidate {
+    fn new(page: SearchResult, name: &str, scientific_name: &str, lang: &str) -> Self {
+        let mut c = Self {
+            page,
+            lang: lang.to_owned(),
+            summary: None,
+            taxon: None,
+            confidence: 0.0,
+            matched_by: vec![],
+        };
+        c.score(name, scientific_name);
+
+        c
+    }
 
-    while start < slice.len() {
-        if let Some(open) = slice.find("<") {
-            if open > start {
-                clean_snippet.push_str(&slice[start..open]);
-            }
+    // score adds up the evidence the candidate is the bird's article
+    fn score(&mut self, name: &str, scientific_name: &str) {
+        let description = self
+            .summary
+            .as_ref()
+            .and_then(|s| s.description.as_deref())
+            .or(self.page.description.as_deref())
+            .unwrap_or_default()
+            .to_lowercase();
+
+        // a disambiguation page lists the bird, it isn't about it
+        if self
+            .summary
+            .as_ref()
+            .is_some_and(|s| s.kind == "disambiguation")
+        {
+            self.confidence = 0.0;
+            self.matched_by = vec![];
+            return;
+        }
+
+        let scientific = scientific_name.to_lowercase();
+        let text = format!(
+            "{} {} {}",
+            html::to_text(&self.page.excerpt),
+            description,
+            self.summary.as_ref().map_or("", |s| s.extract.as_str())
+        )
+        .to_lowercase();
+
+        let mut signals = vec![];
+        if self
+            .taxon
+            .as_deref()
+            .is_some_and(|t| same_name(t, scientific_name))
+        {
+            signals.push(Signal::TaxonName);
+        }
+        if same_name(&self.page.title, scientific_name)
+            || self
+                .page
+                .matched_title
+                .as_deref()
+                .is_some_and(|t| same_name(t, scientific_name))
+        {
+            signals.push(Signal::ScientificTitle);
+        }
+        if !scientific.is_empty() && text.contains(&scientific) {
+            signals.push(Signal::ScientificText);
+        }
+        if description
+            .split(|c: char| !c.is_alphanumeric())
+            .any(|w| bird_words(&self.lang).contains(&w))
+        {
+            signals.push(Signal::BirdDescription);
+        }
+        if same_name(&self.page.title, name) {
+            signals.push(Signal::CommonTitle);
+        }
 
-            slice = &slice[open + 1..];
+        self.confidence = signals.iter().map(Signal::weight).sum::<f32>().min(1.0);
+        self.matched_by = signals;
+    }
 
-            if let Some(close) = slice.find(">") {
-                start = 0;
-                slice = &slice[close + 1..];
-            } else {
-                break;
-            }
-        } else {
-            clean_snippet.push_str(&slice[start..]);
-            break;
+    // into_info puts together what we know about the page, preferring the
+    // summary since the search only has an excerpt and a tiny thumbnail
+    fn into_info(self) -> WikiInfo {
+        let page = self.page;
+        let url = page_url(&self.lang, &page.key);
+
+        let (summary, summary_html, description, thumbnail) = match self.summary {
+            Some(s) => (
+                // the html keeps the paragraph breaks the plain extract loses
+                s.extract_html
+                    .as_deref()
+                    .map(html::to_text)
+                    .filter(|text| !text.is_empty())
+                    .unwrap_or(s.extract),
+                s.extract_html,
+                s.description.or(page.description),
+                s.thumbnail
+                    .map(|t| Image {
+                        url: t.source,
+                        width: t.width,
+                        height: t.height,
+                    })
+                    .or_else(|| page.thumbnail.and_then(Thumbnail::into_image)),
+            ),
+            // the search excerpt still beats nothing when the summary
+            // wouldn't load
+            None => (
+                html::ellipsize(&html::to_text(&page.excerpt)),
+                None,
+                page.description,
+                page.thumbnail.and_then(Thumbnail::into_image),
+            ),
+        };
+
+        WikiInfo {
+            title: page.title,
+            summary,
+            summary_html,
+            description,
+            thumbnail,
+            url,
+            lang: self.lang,
+            confidence: self.confidence,
+            matched_by: self.matched_by,
         }
     }
+}
 
-    clean_snippet.push_str("...");
+// bird_words are how article descriptions say "bird" in the languages people
+// have asked for. Anything else is matched against English, which some
+// Wikipedias use for descriptions they haven't translated
+fn bird_words(lang: &str) -> &'static [&'static str] {
+    match lang {
+        "es" | "pt" => &["ave", "aves"],
+        "fr" => &["oiseau", "oiseaux"],
+        "de" => &["vogel", "vogelart", "vögel"],
+        _ => &["bird", "birds"],
+    }
+}
 
-    clean_snippet
+// same_name compares article titles and names, which differ in case and use
+// underscores for spaces in keys
+fn same_name(a: &str, b: &str) -> bool {
+    let clean = |s: &str| s.trim().replace('_', " ").to_lowercase();
+    !a.trim().is_empty() && clean(a) == clean(b)
 }
 
 #[derive(Deserialize)]
@@ -188,22 +697,279 @@ struct PagesResult {
 
 #[derive(Deserialize)]
 struct SearchResult {
-    id: usize,
     key: String,
     title: String,
-    excerpt: String,
+    // title of the redirect the search matched, e.g. "Turdus migratorius"
+    // when searching for the scientific name lands on "American robin"
     matched_title: Option<String>,
+    // "excerpt": "<span class=\"searchmatch\">Earth</span> is the third planet from the Sun and the only astronomical object known to harbor life. About 29% of <span class=\"searchmatch\">Earth</span>'s surface is land consisting of continents",
+    excerpt: String,
     description: Option<String>,
     thumbnail: Option<Thumbnail>,
-    // "excerpt": "<span class=\"searchmatch\">Earth</span> is the third planet from the Sun and the only astronomical object known to harbor life. About 29% of <span class=\"searchmatch\">Earth</span>'s surface is land consisting of continents",
 }
 
 #[derive(Deserialize)]
 struct Thumbnail {
-    mimetype: String,
-    size: Option<usize>,
-    width: Option<usize>,
-    height: Option<usize>,
-    duration: Option<usize>,
+    width: Option<u32>,
+    height: Option<u32>,
+    // protocol relative, e.g. "//upload.wikimedia.org/..."
     url: String,
 }
+
+impl Thumbnail {
+    fn into_image(self) -> Option<Image> {
+        Some(Image {
+            url: match self.url.starts_with("//") {
+                true => format!("https:{}", self.url),
+                false => self.url,
+            },
+            width: self.width?,
+            height: self.height?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct PageSummary {
+    // "standard" for regular articles, or e.g. "disambiguation"
+    #[serde(rename = "type", default)]
+    kind: String,
+    // lead paragraph as plain text
+    extract: String,
+    // lead paragraphs as html
+    #[serde(default)]
+    extract_html: Option<String>,
+    // Wikidata item the article is about, e.g. "Q934460"
+    wikibase_item: Option<String>,
+    description: Option<String>,
+    thumbnail: Option<SummaryImage>,
+}
+
+#[derive(Deserialize)]
+struct SummaryImage {
+    source: String,
+    width: u32,
+    height: u32,
+}
+
+#[derive(Deserialize)]
+struct ClaimsResponse {
+    #[serde(default)]
+    claims: HashMap<String, Vec<Claim>>,
+}
+
+#[derive(Deserialize)]
+struct Claim {
+    mainsnak: Snak,
+}
+
+#[derive(Deserialize)]
+struct Snak {
+    // missing for "no value" and "unknown value" claims
+    datavalue: Option<DataValue>,
+}
+
+#[derive(Deserialize)]
+struct DataValue {
+    value: serde_json::Value,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_links() {
+        assert_eq!(
+            page_url("en", "American_robin"),
+            "https://en.wikipedia.org/wiki/American_robin"
+        );
+        assert_eq!(
+            page_url("en", "What?_(film)#Plot_&_100%"),
+            "https://en.wikipedia.org/wiki/What%3F_(film)%23Plot_&_100%25"
+        );
+    }
+
+    #[test]
+    fn token_freshness() {
+        let now = Utc::now();
+        let token = AccessToken::from_response(
+            WikiAuthResponse {
+                access_token: "abc".to_owned(),
+                expires_in: 3600,
+            },
+            now,
+        );
+
+        assert!(token.is_fresh(now));
+        assert!(token.is_fresh(now + Duration::seconds(3600 - REFRESH_MARGIN_SECS - 1)));
+        assert!(!token.is_fresh(now + Duration::seconds(3600 - REFRESH_MARGIN_SECS)));
+        assert!(!token.is_fresh(now + Duration::seconds(3600)));
+    }
+
+    #[tokio::test]
+    async fn get_many_answers_every_name() {
+        // a concurrency of 0 would never run anything if it were taken as is
+        let wiki = WikiService::new(
+            String::new(),
+            String::new(),
+            0,
+            std::time::Duration::from_secs(1),
+            true,
+        )
+        .await;
+
+        let birds = vec![
+            ("Mallard".to_owned(), "Anas platyrhynchos".to_owned()),
+            ("American Robin".to_owned(), "Turdus migratorius".to_owned()),
+        ];
+        let infos = wiki.get_many(&birds, "en").await;
+        assert_eq!(infos.len(), 2);
+        assert!(infos.iter().all(|i| matches!(i, Err(WikiError::Offline))));
+    }
+
+    fn page(key: &str, description: &str) -> SearchResult {
+        SearchResult {
+            key: key.to_owned(),
+            title: key.replace('_', " "),
+            matched_title: None,
+            excerpt: format!("<span class=\"searchmatch\">{}</span> is", key),
+            description: Some(description.to_owned()),
+            thumbnail: Some(Thumbnail {
+                width: Some(60),
+                height: Some(45),
+                url: "//upload.wikimedia.org/thumb.jpg".to_owned(),
+            }),
+        }
+    }
+
+    fn summary(kind: &str, extract: &str) -> PageSummary {
+        PageSummary {
+            kind: kind.to_owned(),
+            extract: extract.to_owned(),
+            extract_html: None,
+            wikibase_item: None,
+            description: None,
+            thumbnail: Some(SummaryImage {
+                source: "https://upload.wikimedia.org/320px.jpg".to_owned(),
+                width: 320,
+                height: 240,
+            }),
+        }
+    }
+
+    #[test]
+    fn scoring_candidates() {
+        let (name, sci) = ("Cardinal", "Cardinalis cardinalis");
+
+        let clergy = Candidate::new(
+            page("Cardinal_(Catholic_Church)", "Senior member of the clergy"),
+            name,
+            sci,
+            "en",
+        );
+        assert!(clergy.confidence < MIN_CONFIDENCE);
+
+        let mut bird = Candidate::new(
+            page("Northern_cardinal", "Species of bird"),
+            name,
+            sci,
+            "en",
+        );
+        assert_eq!(bird.matched_by, vec![Signal::BirdDescription]);
+
+        bird.summary = Some(summary(
+            "standard",
+            "The northern cardinal (Cardinalis cardinalis) is a bird.",
+        ));
+        bird.taxon = Some("Cardinalis cardinalis".to_owned());
+        bird.score(name, sci);
+        assert_eq!(
+            bird.matched_by,
+            vec![
+                Signal::TaxonName,
+                Signal::ScientificText,
+                Signal::BirdDescription
+            ]
+        );
+        assert_eq!(bird.confidence, 1.0);
+
+        // searching by scientific name lands on the article through a redirect
+        let mut redirect = page("Northern_cardinal", "Species of bird");
+        redirect.matched_title = Some("Cardinalis cardinalis".to_owned());
+        let redirect = Candidate::new(redirect, name, sci, "en");
+        assert!(redirect.matched_by.contains(&Signal::ScientificTitle));
+        assert!(redirect.confidence >= ACCEPT_CONFIDENCE);
+
+        let mut listing = Candidate::new(
+            page("Cardinal", "Topics referred to by the same term"),
+            name,
+            sci,
+            "en",
+        );
+        listing.summary = Some(summary(
+            "disambiguation",
+            "Cardinal or The Cardinal may refer to: a bird",
+        ));
+        listing.score(name, sci);
+        assert_eq!(listing.confidence, 0.0);
+    }
+
+    #[test]
+    fn info_prefers_the_summary() {
+        let (name, sci) = ("American Robin", "Turdus migratorius");
+
+        let mut c = Candidate::new(page("American_robin", "Species of bird"), name, sci, "en");
+        c.summary = Some(summary(
+            "standard",
+            "The American robin is a migratory songbird.",
+        ));
+        let info = c.into_info();
+        assert_eq!(info.url, "https://en.wikipedia.org/wiki/American_robin");
+        assert_eq!(info.summary, "The American robin is a migratory songbird.");
+        assert_eq!(info.description.as_deref(), Some("Species of bird"));
+        assert_eq!(info.thumbnail.as_ref().map(|t| t.width), Some(320));
+        assert_eq!(
+            info.matched_by,
+            vec![Signal::BirdDescription, Signal::CommonTitle]
+        );
+
+        // the html extract keeps its paragraphs
+        let mut c = Candidate::new(page("American_robin", "Species of bird"), name, sci, "en");
+        let mut s = summary("standard", "The American robin is a songbird. It sings.");
+        s.extract_html =
+            Some("<p>The <b>American robin</b> is a songbird.</p><p>It sings.</p>".to_owned());
+        c.summary = Some(s);
+        let info = c.into_info();
+        assert_eq!(
+            info.summary,
+            "The American robin is a songbird.\n\nIt sings."
+        );
+        assert!(info.summary_html.is_some());
+
+        // the excerpt stops mid sentence, so it's marked as cut short
+        let info =
+            Candidate::new(page("American_robin", "Species of bird"), name, sci, "en").into_info();
+        assert_eq!(info.summary, "American_robin…");
+        assert!(info.summary_html.is_none());
+        let thumbnail = info.thumbnail.unwrap();
+        assert_eq!(thumbnail.url, "https://upload.wikimedia.org/thumb.jpg");
+        assert_eq!((thumbnail.width, thumbnail.height), (60, 45));
+
+        // other languages link to their own Wikipedia and describe birds in
+        // their own words
+        let info = Candidate::new(
+            page("Turdus_migratorius", "Especie de ave"),
+            "Zorzal petirrojo",
+            sci,
+            "es",
+        )
+        .into_info();
+        assert_eq!(info.url, "https://es.wikipedia.org/wiki/Turdus_migratorius");
+        assert_eq!(info.lang, "es");
+        assert_eq!(
+            info.matched_by,
+            vec![Signal::ScientificTitle, Signal::BirdDescription]
+        );
+    }
+}
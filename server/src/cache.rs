@@ -0,0 +1,421 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::store;
+
+// Cache keeps slow changing upstream data (species lists, taxonomy) around so
+// we aren't spending eBird quota on things that change about once a year.
+// Lookups go through a small in-memory LRU first and fall back to an on-disk
+// store that survives restarts. Both tiers expire entries after the same ttl,
+// apart from permanent ones which only live on disk and are kept for
+// PERMANENT_DAYS. Stale entries are swept off the disk now and then so it
+// doesn't keep growing
+pub struct Cache {
+    memory: Mutex<Lru>,
+    disk: Option<sled::Db>,
+    ttl: Duration,
+    // writes to disk since it was opened, see SWEEP_EVERY
+    writes: AtomicUsize,
+}
+
+// how long permanent entries are kept. The frequency chart looks back at most
+// five full years, so anything older than six is never read again
+const PERMANENT_DAYS: i64 = 6 * 366;
+
+// the on-disk store is swept when it's opened and again after this many
+// writes, for servers that stay up for months
+const SWEEP_EVERY: usize = 1000;
+
+// what gets written to disk, so the age of an entry survives a restart
+#[derive(Serialize, Deserialize)]
+struct StoredEntry {
+    stored_at: i64,
+    // skips the ttl, see insert_permanent
+    #[serde(default)]
+    permanent: bool,
+    value: Vec<u8>,
+}
+
+impl Cache {
+    // new opens the on-disk store at path when one is given. A store that
+    // can't be opened is logged and skipped so the server still runs with
+    // just the in-memory tier
+    pub fn new(capacity: usize, ttl: Duration, path: Option<&Path>) -> Self {
+        let disk = match path {
+            Some(p) => match store::open(p) {
+                Ok(db) => Some(db),
+                Err(e) => {
+                    println!("Unable to open the cache at {}: {}", p.display(), e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let cache = Self {
+            memory: Mutex::new(Lru::new(capacity)),
+            disk,
+            ttl,
+            writes: AtomicUsize::new(0),
+        };
+        cache.sweep(Utc::now());
+
+        cache
+    }
+
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.get_at(key, Utc::now())
+    }
+
+    pub fn insert<T: Serialize>(&self, key: &str, value: &T) {
+        self.insert_at(key, value, Utc::now(), false)
+    }
+
+    // insert_permanent stores something that will never change, e.g. what was
+    // seen on a past day. It's kept on disk past the ttl, for PERMANENT_DAYS,
+    // and left out of the in-memory tier so it can't crowd out everything
+    // else. Without an on-disk store it's cached like any other entry
+    pub fn insert_permanent<T: Serialize>(&self, key: &str, value: &T) {
+        self.insert_at(key, value, Utc::now(), true)
+    }
+
+    // invalidate drops every entry whose key starts with prefix from both
+    // tiers, or everything when there's no prefix. Returns how many entries
+    // were removed from the on-disk store, or from memory without one
+    pub fn invalidate(&self, prefix: Option<&str>) -> usize {
+        let prefix = prefix.unwrap_or("");
+
+        let in_memory = self
+            .memory
+            .lock()
+            .expect("locking the cache")
+            .remove_prefix(prefix);
+
+        let db = match &self.disk {
+            Some(db) => db,
+            None => return in_memory,
+        };
+
+        let keys: Vec<sled::IVec> = db
+            .scan_prefix(prefix)
+            .keys()
+            .filter_map(|k| k.ok())
+            .collect();
+        for key in &keys {
+            if let Err(e) = db.remove(key) {
+                println!("Unable to remove a cache entry: {}", e);
+            }
+        }
+
+        keys.len()
+    }
+
+    fn get_at<T: DeserializeOwned>(&self, key: &str, now: DateTime<Utc>) -> Option<T> {
+        let oldest = (now - self.ttl).timestamp();
+
+        let hit = self
+            .memory
+            .lock()
+            .expect("locking the cache")
+            .get(key, oldest);
+        if let Some(bytes) = hit {
+            return serde_json::from_slice(&bytes).ok();
+        }
+
+        // fall back to disk and promote a fresh entry back into memory
+        let stored = self.disk.as_ref()?.get(key).ok()??;
+        let entry: StoredEntry = serde_json::from_slice(&stored).ok()?;
+        if !self.is_fresh(&entry, now) {
+            return None;
+        }
+        if entry.permanent {
+            return serde_json::from_slice(&entry.value).ok();
+        }
+
+        let value = serde_json::from_slice(&entry.value).ok();
+        self.memory
+            .lock()
+            .expect("locking the cache")
+            .insert(key, entry.value, entry.stored_at);
+
+        value
+    }
+
+    fn insert_at<T: Serialize>(&self, key: &str, value: &T, now: DateTime<Utc>, permanent: bool) {
+        let bytes = match serde_json::to_vec(value) {
+            Ok(b) => b,
+            Err(e) => {
+                println!("Unable to serialize {} for the cache: {}", key, e);
+                return;
+            }
+        };
+
+        if let Some(db) = &self.disk {
+            let entry = StoredEntry {
+                stored_at: now.timestamp(),
+                permanent,
+                value: bytes.clone(),
+            };
+            let written = serde_json::to_vec(&entry)
+                .map_err(|e| e.to_string())
+                .and_then(|e| db.insert(key, e).map_err(|e| e.to_string()));
+            if let Err(e) = written {
+                println!("Unable to write {} to the cache: {}", key, e);
+            }
+            if self.writes.fetch_add(1, Ordering::Relaxed) % SWEEP_EVERY == SWEEP_EVERY - 1 {
+                self.sweep(now);
+            }
+            if permanent {
+                return;
+            }
+        }
+
+        self.memory
+            .lock()
+            .expect("locking the cache")
+            .insert(key, bytes, now.timestamp());
+    }
+
+    fn is_fresh(&self, entry: &StoredEntry, now: DateTime<Utc>) -> bool {
+        let keep = match entry.permanent {
+            true => Duration::days(PERMANENT_DAYS),
+            false => self.ttl,
+        };
+
+        entry.stored_at >= (now - keep).timestamp()
+    }
+
+    // sweep removes entries that have gone stale from the on-disk store,
+    // along with any it can't read. Returns how many were removed
+    fn sweep(&self, now: DateTime<Utc>) -> usize {
+        let db = match &self.disk {
+            Some(db) => db,
+            None => return 0,
+        };
+
+        let stale: Vec<sled::IVec> = db
+            .iter()
+            .filter_map(|kv| kv.ok())
+            .filter(|(_, v)| match serde_json::from_slice::<StoredEntry>(v) {
+                Ok(entry) => !self.is_fresh(&entry, now),
+                Err(_) => true,
+            })
+            .map(|(k, _)| k)
+            .collect();
+        for key in &stale {
+            if let Err(e) = db.remove(key) {
+                println!("Unable to remove a cache entry: {}", e);
+            }
+        }
+
+        stale.len()
+    }
+}
+
+// sled only flushes to disk every so often, so make sure the latest writes
+// land before the store goes away
+impl Drop for Cache {
+    fn drop(&mut self) {
+        if let Some(db) = &self.disk {
+            if let Err(e) = db.flush() {
+                println!("Unable to flush the cache: {}", e);
+            }
+        }
+    }
+}
+
+// Lru is a fixed size map that throws out the least recently used entry
+// once it's full
+struct Lru {
+    entries: HashMap<String, LruEntry>,
+    cap: usize,
+    // bumped on every access so entries can be ordered by last use
+    tick: u64,
+}
+
+struct LruEntry {
+    value: Vec<u8>,
+    stored_at: i64,
+    last_used: u64,
+}
+
+impl Lru {
+    fn new(cap: usize) -> Self {
+        Self {
+            entries: HashMap::with_capacity(cap),
+            cap,
+            tick: 0,
+        }
+    }
+
+    // get hands back the entry when it was stored at or after oldest, and
+    // drops it when it has gone stale
+    fn get(&mut self, key: &str, oldest: i64) -> Option<Vec<u8>> {
+        self.tick += 1;
+
+        let entry = self.entries.get_mut(key)?;
+        if entry.stored_at < oldest {
+            self.entries.remove(key);
+            return None;
+        }
+
+        entry.last_used = self.tick;
+        Some(entry.value.clone())
+    }
+
+    fn insert(&mut self, key: &str, value: Vec<u8>, stored_at: i64) {
+        if self.cap == 0 {
+            return;
+        }
+
+        self.tick += 1;
+
+        if !self.entries.contains_key(key) && self.entries.len() >= self.cap {
+            let lru = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone());
+            if let Some(k) = lru {
+                self.entries.remove(&k);
+            }
+        }
+
+        self.entries.insert(
+            key.to_owned(),
+            LruEntry {
+                value,
+                stored_at,
+                last_used: self.tick,
+            },
+        );
+    }
+
+    fn remove_prefix(&mut self, prefix: &str) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|k, _| !k.starts_with(prefix));
+
+        before - self.entries.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let mut lru = Lru::new(2);
+
+        lru.insert("a", vec![1], 0);
+        lru.insert("b", vec![2], 0);
+        assert_eq!(lru.get("a", 0), Some(vec![1]));
+
+        // b is now the least recently used
+        lru.insert("c", vec![3], 0);
+        assert_eq!(lru.get("b", 0), None);
+        assert_eq!(lru.get("a", 0), Some(vec![1]));
+        assert_eq!(lru.get("c", 0), Some(vec![3]));
+    }
+
+    #[test]
+    fn expired_entries_are_misses() {
+        let cache = Cache::new(10, Duration::seconds(60), None);
+        let now = Utc::now();
+
+        cache.insert_at("spplist:US-NY", &vec!["amerob"], now, false);
+        assert_eq!(
+            cache.get_at::<Vec<String>>("spplist:US-NY", now + Duration::seconds(59)),
+            Some(vec!["amerob".to_owned()])
+        );
+        assert_eq!(
+            cache.get_at::<Vec<String>>("spplist:US-NY", now + Duration::seconds(61)),
+            None
+        );
+    }
+
+    #[test]
+    fn permanent_entries_skip_the_ttl() {
+        let path = std::env::temp_dir().join(format!(
+            "birdme-cache-permanent-test-{}",
+            std::process::id()
+        ));
+        let cache = Cache::new(10, Duration::days(1), Some(&path));
+        let now = Utc::now();
+
+        cache.insert_at("historic:US-NY:2023-05-01", &vec!["amerob"], now, true);
+        assert_eq!(
+            cache.get_at::<Vec<String>>("historic:US-NY:2023-05-01", now + Duration::days(1500)),
+            Some(vec!["amerob".to_owned()])
+        );
+        // and they stay out of memory
+        assert!(cache.memory.lock().unwrap().entries.is_empty());
+
+        drop(cache);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn sweeps_stale_entries() {
+        let path =
+            std::env::temp_dir().join(format!("birdme-cache-sweep-test-{}", std::process::id()));
+        let cache = Cache::new(10, Duration::days(1), Some(&path));
+        let now = Utc::now();
+
+        cache.insert_at("spplist:US-NY", &"old", now - Duration::days(2), false);
+        cache.insert_at("spplist:US-CA", &"new", now, false);
+        cache.insert_at(
+            "historic:US-NY:2023-05-01",
+            &"kept",
+            now - Duration::days(2),
+            true,
+        );
+        cache.insert_at(
+            "historic:US-NY:2020-05-01",
+            &"gone",
+            now - Duration::days(PERMANENT_DAYS + 1),
+            true,
+        );
+
+        assert_eq!(cache.sweep(now), 2);
+        let db = cache.disk.as_ref().unwrap();
+        let mut left: Vec<String> = db
+            .iter()
+            .keys()
+            .map(|k| String::from_utf8(k.unwrap().to_vec()).unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(left, vec!["historic:US-NY:2023-05-01", "spplist:US-CA"]);
+
+        drop(cache);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+
+    #[test]
+    fn survives_reopening() {
+        let path = std::env::temp_dir().join(format!("birdme-cache-test-{}", std::process::id()));
+
+        let cache = Cache::new(10, Duration::days(1), Some(&path));
+        cache.insert("taxonomy:amerob", &"American Robin");
+        // dropping flushes and closes the only handle on the store, opening it
+        // again waits for sled to let go of the lock
+        drop(cache);
+
+        let cache = Cache::new(10, Duration::days(1), Some(&path));
+        assert_eq!(
+            cache.get::<String>("taxonomy:amerob"),
+            Some("American Robin".to_owned())
+        );
+
+        assert_eq!(cache.invalidate(Some("taxonomy:")), 1);
+        assert_eq!(cache.get::<String>("taxonomy:amerob"), None);
+
+        drop(cache);
+        let _ = std::fs::remove_dir_all(&path);
+    }
+}
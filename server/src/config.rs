@@ -1,22 +1,194 @@
-use crate::api::{ebird::EbirdService, wiki::WikiService};
+use chrono::Duration;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::api::ebird::{EbirdService, FrequencySampling};
+use crate::api::wiki::WikiService;
+use crate::cache::Cache;
+use crate::history::History;
+use crate::provider::local::LocalSpecies;
+use crate::provider::{DescriptionProvider, NoDescriptions, SpeciesProvider};
+use crate::taxonomy::TaxonomyIndex;
+
+// defaults for the eBird cache when the environment doesn't say otherwise
+const DEFAULT_CACHE_PATH: &str = ".cache/ebird";
+const DEFAULT_CACHE_SIZE: usize = 1000;
+const DEFAULT_CACHE_TTL_DAYS: i64 = 30;
+
+// defaults for Wikipedia lookups
+const DEFAULT_WIKI_CONCURRENCY: usize = 4;
+const DEFAULT_WIKI_TIMEOUT_SECS: u64 = 5;
+
+// defaults for the bird of the day history
+const DEFAULT_HISTORY_PATH: &str = ".cache/history";
+const DEFAULT_NO_REPEAT_DAYS: u32 = 30;
+
+// defaults for the frequency chart, six days behind every week
+const DEFAULT_FREQUENCY_SAMPLES: u32 = 2;
+const DEFAULT_FREQUENCY_YEARS: u32 = 3;
 
 pub struct ServiceConfig {
-    pub wiki: WikiService,
-    pub ebird: EbirdService,
+    // where birds come from, picked by SPECIES_PROVIDER
+    pub species: Arc<dyn SpeciesProvider>,
+    // where blurbs come from, picked by DESCRIPTION_PROVIDER
+    pub descriptions: Box<dyn DescriptionProvider>,
+    // sightings, hotspots and regions only come from eBird. Shared with
+    // species when that's eBird too
+    pub ebird: Arc<EbirdService>,
+    // past birds of the day, so they stay put and don't repeat whichever
+    // provider picked them
+    pub history: History,
+    // token the admin routes expect in the X-Admin-Token header. The admin
+    // routes are turned off when it isn't set
+    pub admin_token: Option<String>,
+    // how many days go into a frequency chart, from FREQUENCY_SAMPLES_PER_WEEK
+    // and FREQUENCY_YEARS
+    pub frequency: FrequencySampling,
 }
 
 // read environment variables to stick into the different services here to be
 // passed to Rocket as State
 impl ServiceConfig {
     pub async fn new() -> Result<Self, std::env::VarError> {
-        let wiki_client_id = std::env::var("WIKI_CLIENT_ID")?;
-        let wiki_client_secret = std::env::var("WIKI_CLIENT_SECRET")?;
+        // OFFLINE=true never calls out to eBird or Wikimedia, which is handy
+        // for demos and tests. The credentials aren't needed then
+        let offline = env_or("OFFLINE", false);
+        let var = |name: &str| match offline {
+            true => Ok(std::env::var(name).unwrap_or_default()),
+            false => std::env::var(name),
+        };
+
+        let ebird_api_key = var("EBIRD_API_KEY")?;
 
-        let ebird_api_key = std::env::var("EBIRD_API_KEY")?;
+        let admin_token = std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty());
+
+        // loaded once and shared, the full taxonomy is tens of thousands of taxa
+        let index = Arc::new(taxonomy());
+
+        let ebird = Arc::new(EbirdService::new(
+            ebird_api_key,
+            ebird_cache(),
+            index.clone(),
+            offline,
+        ));
+
+        let species: Arc<dyn SpeciesProvider> =
+            match env_or("SPECIES_PROVIDER", "ebird".to_owned()).as_str() {
+                "local" => {
+                    if index.is_empty() {
+                        println!("The local species provider needs TAXONOMY_FILE, using eBird");
+                        ebird.clone()
+                    } else {
+                        Arc::new(LocalSpecies::new(index))
+                    }
+                }
+                "ebird" => ebird.clone(),
+                other => {
+                    println!("Unknown SPECIES_PROVIDER {}, using eBird", other);
+                    ebird.clone()
+                }
+            };
+
+        let descriptions: Box<dyn DescriptionProvider> =
+            match env_or("DESCRIPTION_PROVIDER", "wikipedia".to_owned()).as_str() {
+                "none" => Box::new(NoDescriptions),
+                other => {
+                    if other != "wikipedia" {
+                        println!("Unknown DESCRIPTION_PROVIDER {}, using Wikipedia", other);
+                    }
+                    Box::new(
+                        WikiService::new(
+                            var("WIKI_CLIENT_ID")?,
+                            var("WIKI_CLIENT_SECRET")?,
+                            env_or("WIKI_CONCURRENCY", DEFAULT_WIKI_CONCURRENCY),
+                            std::time::Duration::from_secs(env_or(
+                                "WIKI_TIMEOUT_SECS",
+                                DEFAULT_WIKI_TIMEOUT_SECS,
+                            )),
+                            offline,
+                        )
+                        .await,
+                    )
+                }
+            };
 
         Ok(Self {
-            wiki: WikiService::new(wiki_client_id, wiki_client_secret).await,
-            ebird: EbirdService::new(ebird_api_key),
+            species,
+            descriptions,
+            ebird,
+            history: history(),
+            admin_token,
+            frequency: FrequencySampling::new(
+                env_or("FREQUENCY_SAMPLES_PER_WEEK", DEFAULT_FREQUENCY_SAMPLES),
+                env_or("FREQUENCY_YEARS", DEFAULT_FREQUENCY_YEARS),
+            ),
         })
     }
 }
+
+// taxonomy loads the eBird taxonomy export named by TAXONOMY_FILE. The server
+// still starts without one, it just has to ask eBird for every species
+fn taxonomy() -> TaxonomyIndex {
+    let path = match std::env::var("TAXONOMY_FILE") {
+        Ok(p) if !p.is_empty() => p,
+        _ => return TaxonomyIndex::default(),
+    };
+
+    match TaxonomyIndex::load(Path::new(&path)) {
+        Ok(index) => {
+            println!("Loaded {} taxa from {}", index.len(), path);
+            index
+        }
+        Err(e) => {
+            println!("Unable to load the taxonomy from {}: {}", path, e);
+            TaxonomyIndex::default()
+        }
+    }
+}
+
+// ebird_cache builds the species list and taxonomy cache from EBIRD_CACHE_PATH,
+// EBIRD_CACHE_SIZE and EBIRD_CACHE_TTL_DAYS. An empty EBIRD_CACHE_PATH keeps
+// the cache in memory only
+fn ebird_cache() -> Cache {
+    let path = std::env::var("EBIRD_CACHE_PATH").unwrap_or_else(|_| DEFAULT_CACHE_PATH.to_owned());
+    let path = if path.is_empty() {
+        None
+    } else {
+        Some(PathBuf::from(path))
+    };
+
+    let size = env_or("EBIRD_CACHE_SIZE", DEFAULT_CACHE_SIZE);
+    let ttl_days = env_or("EBIRD_CACHE_TTL_DAYS", DEFAULT_CACHE_TTL_DAYS);
+
+    Cache::new(size, Duration::days(ttl_days), path.as_deref())
+}
+
+// history opens the bird of the day history from HISTORY_PATH, where birds
+// sit out NO_REPEAT_DAYS days after being picked. An empty HISTORY_PATH keeps
+// it in memory only
+fn history() -> History {
+    let path = std::env::var("HISTORY_PATH").unwrap_or_else(|_| DEFAULT_HISTORY_PATH.to_owned());
+    let path = if path.is_empty() {
+        None
+    } else {
+        Some(PathBuf::from(path))
+    };
+
+    History::new(
+        path.as_deref(),
+        env_or("NO_REPEAT_DAYS", DEFAULT_NO_REPEAT_DAYS),
+    )
+}
+
+// env_or parses an environment variable, falling back to default when it's
+// missing or doesn't parse
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name).ok().map(|v| v.parse::<T>()) {
+        Some(Ok(v)) => v,
+        Some(Err(_)) => {
+            println!("Ignoring {}, it isn't a valid value", name);
+            default
+        }
+        None => default,
+    }
+}
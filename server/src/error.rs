@@ -0,0 +1,144 @@
+use birdme_types::ErrorBody;
+use rocket::http::{Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use std::fmt;
+
+use crate::api::ebird::EbirdError;
+use crate::provider::ProviderError;
+
+// ApiError is everything a route can hand back to the client instead of a
+// successful response. Each variant maps onto its own status code and is sent
+// with an ErrorBody so clients can tell them apart
+#[derive(Debug)]
+pub enum ApiError {
+    // the caller is making requests too quickly, retry after the given seconds
+    RateLimited(i64),
+    // the admin token is missing or wrong
+    Unauthorized,
+    // the region isn't shaped like an eBird region code
+    BadRegion(String),
+    // one of the query parameters is out of range
+    BadQuery(String),
+    // the region is valid but eBird has no species recorded for it
+    NoSpecies(String),
+    // the thing being looked up doesn't exist
+    NotFound(String),
+    // an upstream service answered, but with something we couldn't use
+    BadGateway(String),
+    // an upstream service couldn't be reached
+    Unavailable(String),
+}
+
+impl ApiError {
+    fn status(&self) -> Status {
+        match self {
+            ApiError::RateLimited(_) => Status::TooManyRequests,
+            ApiError::Unauthorized => Status::Unauthorized,
+            ApiError::BadRegion(_) | ApiError::BadQuery(_) => Status::BadRequest,
+            ApiError::NoSpecies(_) | ApiError::NotFound(_) => Status::NotFound,
+            ApiError::BadGateway(_) => Status::BadGateway,
+            ApiError::Unavailable(_) => Status::ServiceUnavailable,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ApiError::RateLimited(_) => "rate_limited",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::BadRegion(_) => "bad_region",
+            ApiError::BadQuery(_) => "bad_query",
+            ApiError::NoSpecies(_) => "no_species",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::BadGateway(_) => "bad_gateway",
+            ApiError::Unavailable(_) => "unavailable",
+        }
+    }
+
+    // from_ebird attaches the region being looked up to an eBird failure
+    pub fn from_ebird(err: EbirdError, region: &str) -> Self {
+        match err {
+            EbirdError::Transport(_) | EbirdError::Offline => {
+                ApiError::Unavailable(err.to_string())
+            }
+            // eBird rejects region codes it doesn't know with a 400
+            EbirdError::Status(400) => ApiError::BadRegion(region.to_owned()),
+            EbirdError::Status(_) | EbirdError::Decode(_) => ApiError::BadGateway(err.to_string()),
+            EbirdError::Empty => ApiError::NoSpecies(region.to_owned()),
+        }
+    }
+
+    // from_provider attaches the region being looked up to a provider failure
+    pub fn from_provider(err: ProviderError, region: &str) -> Self {
+        match err {
+            ProviderError::Unavailable(_) | ProviderError::Disabled(_) => {
+                ApiError::Unavailable(err.to_string())
+            }
+            ProviderError::BadRequest(_) => ApiError::BadRegion(region.to_owned()),
+            ProviderError::BadResponse(e) => ApiError::BadGateway(e),
+            ProviderError::NotFound => ApiError::NoSpecies(region.to_owned()),
+        }
+    }
+}
+
+// used for lookups that aren't tied to a region, where an empty result is
+// just an empty list rather than a missing region
+impl From<EbirdError> for ApiError {
+    fn from(err: EbirdError) -> Self {
+        match err {
+            EbirdError::Transport(_) | EbirdError::Offline => {
+                ApiError::Unavailable(err.to_string())
+            }
+            _ => ApiError::BadGateway(err.to_string()),
+        }
+    }
+}
+
+impl From<ProviderError> for ApiError {
+    fn from(err: ProviderError) -> Self {
+        match err {
+            ProviderError::Unavailable(_) | ProviderError::Disabled(_) => {
+                ApiError::Unavailable(err.to_string())
+            }
+            _ => ApiError::BadGateway(err.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApiError::RateLimited(secs) => {
+                write!(f, "too many requests, try again in {} seconds", secs)
+            }
+            ApiError::Unauthorized => write!(f, "a valid admin token is required"),
+            ApiError::BadRegion(region) => {
+                write!(f, "{} is not a valid eBird region code", region)
+            }
+            ApiError::BadQuery(e) => write!(f, "invalid query: {}", e),
+            ApiError::NoSpecies(region) => write!(f, "no species found for region {}", region),
+            ApiError::NotFound(e) => write!(f, "{}", e),
+            ApiError::BadGateway(e) => write!(f, "bad response from upstream service: {}", e),
+            ApiError::Unavailable(e) => write!(f, "upstream service unavailable: {}", e),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let body = ErrorBody {
+            error: self.name().to_owned(),
+            message: self.to_string(),
+        };
+
+        let mut res = Response::build_from(Json(body).respond_to(req)?);
+        res.status(self.status());
+
+        if let ApiError::RateLimited(secs) = self {
+            res.header(Header::new("Retry-After", secs.to_string()));
+        }
+
+        res.ok()
+    }
+}
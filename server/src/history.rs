@@ -0,0 +1,172 @@
+use chrono::{Days, NaiveDate};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::api::ebird::Bird;
+use crate::provider::{ProviderError, SpeciesProvider};
+use crate::store;
+
+// History remembers which species was the bird of the day for each region and
+// date, so the pick stays put for the rest of the day and doesn't come back
+// around for a while. Unlike the cache nothing here expires or gets cleared
+// by the admin route, since losing it would change today's bird midday
+pub struct History {
+    memory: Mutex<HashMap<String, String>>,
+    disk: Option<sled::Db>,
+    // how many days a species has to sit out after being picked
+    days: u32,
+}
+
+impl History {
+    // new opens the on-disk store at path when one is given, falling back to
+    // memory only when it can't be opened
+    pub fn new(path: Option<&Path>, days: u32) -> Self {
+        let disk = match path {
+            Some(p) => match store::open(p) {
+                Ok(db) => Some(db),
+                Err(e) => {
+                    println!("Unable to open the history at {}: {}", p.display(), e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        Self {
+            memory: Mutex::new(HashMap::new()),
+            disk,
+            days,
+        }
+    }
+
+    // bird_of_the_day hands back the bird picked for region on date, asking
+    // species to pick one that hasn't been on lately the first time around
+    pub async fn bird_of_the_day(
+        &self,
+        species: &dyn SpeciesProvider,
+        region: &str,
+        date: NaiveDate,
+    ) -> Result<Bird, ProviderError> {
+        if let Some(code) = self.get(region, date) {
+            return species.species(&code).await;
+        }
+
+        let bird = species
+            .bird_of_the_day(region, date, &self.recent(region, date))
+            .await?;
+        self.record(region, date, &bird.species_code);
+
+        Ok(bird)
+    }
+
+    // get hands back the species code picked for region on date, if any
+    pub fn get(&self, region: &str, date: NaiveDate) -> Option<String> {
+        let key = key(region, date);
+
+        if let Some(code) = self.memory.lock().expect("locking the history").get(&key) {
+            return Some(code.clone());
+        }
+
+        let stored = self.disk.as_ref()?.get(&key).ok()??;
+        let code = String::from_utf8(stored.to_vec()).ok()?;
+        self.memory
+            .lock()
+            .expect("locking the history")
+            .insert(key, code.clone());
+
+        Some(code)
+    }
+
+    pub fn record(&self, region: &str, date: NaiveDate, code: &str) {
+        let key = key(region, date);
+
+        // picks only happen once a day per region, so flushing each one right
+        // away is cheap and keeps a crash from changing today's bird
+        if let Some(db) = &self.disk {
+            let written = db.insert(&key, code.as_bytes()).and_then(|_| db.flush());
+            if let Err(e) = written {
+                println!("Unable to write {} to the history: {}", key, e);
+            }
+        }
+
+        self.memory
+            .lock()
+            .expect("locking the history")
+            .insert(key, code.to_owned());
+    }
+
+    // recent lists the codes picked for region in the days leading up to
+    // date, which shouldn't be picked again yet
+    pub fn recent(&self, region: &str, date: NaiveDate) -> Vec<String> {
+        (1..=self.days)
+            .filter_map(|n| date.checked_sub_days(Days::new(n.into())))
+            .filter_map(|d| self.get(region, d))
+            .collect()
+    }
+}
+
+fn key(region: &str, date: NaiveDate) -> String {
+    format!("{}:{}", region, date.format("%Y-%m-%d"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::local::LocalSpecies;
+    use crate::taxonomy::TaxonomyIndex;
+    use std::sync::Arc;
+
+    #[test]
+    fn recent_picks() {
+        let history = History::new(None, 3);
+        let day = |d: u32| NaiveDate::from_ymd_opt(2024, 5, d).unwrap();
+
+        history.record("US-NY", day(1), "amerob");
+        history.record("US-NY", day(3), "blujay");
+        history.record("US-NY", day(5), "norcar");
+        history.record("US-CA", day(4), "calqua");
+
+        assert_eq!(history.get("US-NY", day(3)), Some("blujay".to_owned()));
+        assert_eq!(history.get("US-NY", day(4)), None);
+
+        // only the three days before count, and never the day itself
+        assert_eq!(history.recent("US-NY", day(5)), vec!["blujay"]);
+        assert_eq!(history.recent("US-NY", day(4)), vec!["blujay", "amerob"]);
+        assert_eq!(history.recent("US-CA", day(5)), vec!["calqua"]);
+    }
+
+    #[tokio::test]
+    async fn birds_of_the_day() {
+        let taxa = r#"[
+            {"sciName":"Turdus migratorius","comName":"American Robin","speciesCode":"amerob","category":"species","taxonOrder":30419.0,"bandingCodes":["AMRO"],"familyCode":"turdid1","familyComName":"Thrushes and Allies","familySciName":"Turdidae"},
+            {"sciName":"Cyanocitta cristata","comName":"Blue Jay","speciesCode":"blujay","category":"species","taxonOrder":21000.0,"bandingCodes":["BLJA"],"familyCode":"corvid1","familyComName":"Crows, Jays, and Magpies","familySciName":"Corvidae"}
+        ]"#;
+        let local = LocalSpecies::new(Arc::new(TaxonomyIndex::from_json(taxa).unwrap()));
+        let history = History::new(None, 1);
+        let day = |d: u32| NaiveDate::from_ymd_opt(2024, 5, d).unwrap();
+
+        let first = history
+            .bird_of_the_day(&local, "US-NY", day(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            history.get("US-NY", day(1)),
+            Some(first.species_code.clone())
+        );
+
+        // the next day has to be the other bird, whatever the seed says
+        let second = history
+            .bird_of_the_day(&local, "US-NY", day(2))
+            .await
+            .unwrap();
+        assert_ne!(second.species_code, first.species_code);
+
+        // and asking again keeps the first day's pick
+        let again = history
+            .bird_of_the_day(&local, "US-NY", day(1))
+            .await
+            .unwrap();
+        assert_eq!(again.species_code, first.species_code);
+    }
+}
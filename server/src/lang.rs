@@ -0,0 +1,111 @@
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+
+use crate::error::ApiError;
+
+// what names and blurbs come back in when the caller doesn't say, and what
+// everything falls back to when there's no translation
+pub const DEFAULT_LANG: &str = "en";
+
+// Lang is a request guard for the language the caller wants bird names and
+// blurbs in. ?lang= wins over the Accept-Language header, and English is used
+// when neither names one
+pub struct Lang(String);
+
+impl Lang {
+    // parse takes the primary language out of a tag like "es" or "fr-CA".
+    // Only the plain two and three letter codes eBird and Wikipedia share
+    // are accepted
+    pub fn parse(tag: &str) -> Option<Self> {
+        let primary = tag.trim().split(['-', '_']).next()?.to_lowercase();
+
+        match (2..=3).contains(&primary.len()) && primary.chars().all(|c| c.is_ascii_lowercase()) {
+            true => Some(Self(primary)),
+            false => None,
+        }
+    }
+
+    // from_accept_language picks the language the caller prefers most out of
+    // an Accept-Language header, e.g. "fr-CA,fr;q=0.9,en;q=0.8"
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut tags: Vec<(f32, &str)> = header
+            .split(',')
+            .filter_map(|part| {
+                let mut pieces = part.split(';');
+                let tag = pieces.next()?.trim();
+                let quality = pieces
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+                Some((quality, tag))
+            })
+            .filter(|(quality, _)| *quality > 0.0)
+            .collect();
+        // stable, so equally preferred languages keep the caller's order
+        tags.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        tags.into_iter().find_map(|(_, tag)| Self::parse(tag))
+    }
+
+    pub fn code(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for Lang {
+    fn default() -> Self {
+        Self(DEFAULT_LANG.to_owned())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Lang {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        // a bad ?lang= was asked for on purpose, so say so. A header the
+        // browser made up just falls back to English
+        if let Some(Ok(tag)) = req.query_value::<&str>("lang") {
+            return match Self::parse(tag) {
+                Some(lang) => Outcome::Success(lang),
+                None => Outcome::Error((
+                    Status::BadRequest,
+                    ApiError::BadQuery(format!("{} isn't a language code like en or es", tag)),
+                )),
+            };
+        }
+
+        let lang = req
+            .headers()
+            .get_one("Accept-Language")
+            .and_then(Self::from_accept_language)
+            .unwrap_or_default();
+
+        Outcome::Success(lang)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        assert_eq!(Lang::parse("es").unwrap().code(), "es");
+        assert_eq!(Lang::parse("fr-CA").unwrap().code(), "fr");
+        assert_eq!(Lang::parse("pt_BR").unwrap().code(), "pt");
+        assert!(Lang::parse("*").is_none());
+        assert!(Lang::parse("english").is_none());
+        assert!(Lang::parse("").is_none());
+    }
+
+    #[test]
+    fn accept_language() {
+        let code = |h: &str| Lang::from_accept_language(h).map(|l| l.code().to_owned());
+
+        assert_eq!(code("fr-CA,fr;q=0.9,en;q=0.8").as_deref(), Some("fr"));
+        assert_eq!(code("en;q=0.5, es").as_deref(), Some("es"));
+        assert_eq!(code("*, de;q=0.7").as_deref(), Some("de"));
+        assert_eq!(code("es;q=0, it;q=0.1").as_deref(), Some("it"));
+        assert_eq!(code("*"), None);
+    }
+}
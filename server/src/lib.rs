@@ -1,4 +1,12 @@
+pub mod admin;
 pub mod api;
+pub mod cache;
 pub mod config;
+pub mod error;
+pub mod history;
+pub mod lang;
 pub mod logger;
+pub mod provider;
 pub mod rate_limiter;
+pub mod store;
+pub mod taxonomy;
@@ -23,7 +23,7 @@ impl Logger {
     pub fn log(&mut self, l: &str) {
         let now = Utc::now();
 
-        let s = format!("{} {}", now.to_string(), l.to_owned());
+        let s = format!("{} {}", now, l);
         self.buf.add(s);
     }
 
@@ -38,7 +38,7 @@ impl Logger {
             println!("{}", s);
 
             log_file
-                .write(s.as_bytes())
+                .write_all(s.as_bytes())
                 .expect("writing to log file failed");
         }
 
@@ -100,7 +100,7 @@ impl RingBuffer {
         self.head = 0;
     }
 
-    pub fn iter(&self) -> RingBufferIterator {
+    pub fn iter(&self) -> RingBufferIterator<'_> {
         RingBufferIterator {
             buffer: self,
             counter: 0,
@@ -165,11 +165,11 @@ mod tests {
     fn iter() {
         let mut logger = Logger::new("blah".to_owned(), 2);
 
-        let logs = vec!["hello", "world"];
+        let logs = ["hello", "world"];
 
-        for i in 0..logs.len() {
-            println!("{}: adding {}", i, logs[i]);
-            logger.log(logs[i]);
+        for (i, l) in logs.iter().enumerate() {
+            println!("{}: adding {}", i, l);
+            logger.log(l);
         }
 
         for (i, s) in logger.buf.iter().enumerate() {
@@ -182,20 +182,18 @@ mod tests {
     fn iter_empty() {
         let logger = Logger::new("blah".to_owned(), 2);
 
-        for _ in logger.buf.iter() {
-            panic!();
-        }
+        assert!(logger.buf.iter().next().is_none());
     }
 
     #[test]
     fn iter_single() {
         let mut logger = Logger::new("blah".to_owned(), 2);
 
-        let logs = vec!["hello"];
+        let logs = ["hello"];
 
-        for i in 0..logs.len() {
-            println!("{}: adding {}", i, logs[i]);
-            logger.log(logs[i]);
+        for (i, l) in logs.iter().enumerate() {
+            println!("{}: adding {}", i, l);
+            logger.log(l);
         }
 
         for (i, s) in logger.buf.iter().enumerate() {
@@ -208,11 +206,11 @@ mod tests {
     fn iter_wrapped() {
         let mut logger = Logger::new("blah".to_owned(), 2);
 
-        let logs = vec!["hello", "world", "I'm", "Yours"];
+        let logs = ["hello", "world", "I'm", "Yours"];
 
-        for i in 0..logs.len() {
-            println!("{}: adding {}", i, logs[i]);
-            logger.log(logs[i]);
+        for (i, l) in logs.iter().enumerate() {
+            println!("{}: adding {}", i, l);
+            logger.log(l);
         }
 
         for (i, s) in logger.buf.iter().enumerate() {
@@ -225,11 +223,11 @@ mod tests {
     fn iter_wrapped2() {
         let mut logger = Logger::new("blah".to_owned(), 2);
 
-        let logs = vec!["hello", "world", "I'm", "Yours", "today"];
+        let logs = ["hello", "world", "I'm", "Yours", "today"];
 
-        for i in 0..logs.len() {
-            println!("{}: adding {}", i, logs[i]);
-            logger.log(logs[i]);
+        for (i, l) in logs.iter().enumerate() {
+            println!("{}: adding {}", i, l);
+            logger.log(l);
         }
 
         for (i, s) in logger.buf.iter().enumerate() {
@@ -10,10 +10,9 @@ extern crate rocket;
 async fn rocket() -> _ {
     println!("Running birdme server...");
     // dotenv().ok();
-    let _ = match dotenv() {
-        Ok(_) => (),
-        Err(e) => println!("dotenv failed {:?}", e),
-    };
+    if let Err(e) = dotenv() {
+        println!("dotenv failed {:?}", e);
+    }
 
     let config = config::ServiceConfig::new().await.unwrap();
 
@@ -27,5 +26,23 @@ async fn rocket() -> _ {
         .manage(config)
         .manage(limiter)
         .manage(logger)
-        .mount("/", routes![routes::get_birds])
+        .mount(
+            "/",
+            routes![
+                routes::get_birds,
+                routes::get_today,
+                routes::get_recent,
+                routes::get_notable,
+                routes::get_species,
+                routes::get_frequency,
+                routes::get_search,
+                routes::get_nearby,
+                routes::get_nearby_hotspots,
+                routes::get_hotspots,
+                routes::get_hotspot,
+                routes::get_regions,
+                routes::get_region,
+                routes::invalidate_cache,
+            ],
+        )
 }
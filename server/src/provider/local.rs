@@ -0,0 +1,167 @@
+use birdme_types::SearchResult;
+use chrono::NaiveDate;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::sync::Arc;
+
+use super::{to_search_result, ProviderError, SpeciesProvider};
+use crate::api::ebird::{choose_random_codes, daily_seed, Bird, BirdFilter};
+use crate::taxonomy::{Taxon, TaxonomyIndex};
+
+// LocalSpecies serves birds straight out of a taxonomy file without asking
+// eBird for anything. The file doesn't say where birds live, so every region
+// gets picks from the whole taxonomy. Also handy as a fixture in tests
+pub struct LocalSpecies {
+    index: Arc<TaxonomyIndex>,
+}
+
+impl LocalSpecies {
+    pub fn new(index: Arc<TaxonomyIndex>) -> Self {
+        Self { index }
+    }
+
+    // pick chooses up to count codes out of the taxa passing keep, in
+    // taxonomic order so the same seed always picks the same birds
+    fn pick(&self, count: usize, seed: Option<u64>, keep: impl Fn(&Taxon) -> bool) -> Vec<Bird> {
+        let codes: Vec<String> = self
+            .index
+            .iter()
+            .filter(|t| keep(t))
+            .map(|t| t.species_code.clone())
+            .collect();
+
+        let mut rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        choose_random_codes(&codes, count, &mut rng)
+            .iter()
+            .filter_map(|code| self.index.by_code(code).map(Bird::from))
+            .collect()
+    }
+}
+
+#[rocket::async_trait]
+impl SpeciesProvider for LocalSpecies {
+    // there are no sightings to weigh picks by, so rarity is ignored
+    async fn random_birds(
+        &self,
+        _region: &str,
+        filter: &BirdFilter,
+    ) -> Result<Vec<Bird>, ProviderError> {
+        let birds = self.pick(filter.count, filter.seed, |t| filter.matches(t));
+
+        match birds.is_empty() {
+            true => Err(ProviderError::NotFound),
+            false => Ok(birds),
+        }
+    }
+
+    // the pick is seeded by region and date like eBird's
+    async fn bird_of_the_day(
+        &self,
+        region: &str,
+        date: NaiveDate,
+        exclude: &[String],
+    ) -> Result<Bird, ProviderError> {
+        let seed = Some(daily_seed(region, date));
+
+        let fresh = self.pick(1, seed, |t| {
+            t.category == "species" && !exclude.contains(&t.species_code)
+        });
+        // a taxonomy with fewer species than days to sit out has to repeat
+        let mut picked = match fresh.is_empty() {
+            true => self.pick(1, seed, |t| t.category == "species"),
+            false => fresh,
+        };
+
+        picked.pop().ok_or(ProviderError::NotFound)
+    }
+
+    async fn species(&self, query: &str) -> Result<Bird, ProviderError> {
+        self.index
+            .resolve(query)
+            .map(Bird::from)
+            .ok_or(ProviderError::NotFound)
+    }
+
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>, ProviderError> {
+        Ok(self
+            .index
+            .search(query, limit)
+            .into_iter()
+            .map(to_search_result)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAXA: &str = r#"[
+        {"sciName":"Turdus migratorius","comName":"American Robin","speciesCode":"amerob","category":"species","taxonOrder":30419.0,"bandingCodes":["AMRO"],"familyCode":"turdid1","familyComName":"Thrushes and Allies","familySciName":"Turdidae"},
+        {"sciName":"Catharus guttatus","comName":"Hermit Thrush","speciesCode":"herthr","category":"species","taxonOrder":30300.0,"bandingCodes":["HETH"],"familyCode":"turdid1","familyComName":"Thrushes and Allies","familySciName":"Turdidae"},
+        {"sciName":"Anas platyrhynchos","comName":"Mallard","speciesCode":"mallar3","category":"species","taxonOrder":316.0,"bandingCodes":["MALL"],"familyCode":"anatid1","familyComName":"Ducks, Geese, and Waterfowl","familySciName":"Anatidae"},
+        {"sciName":"Anas sp.","comName":"duck sp.","speciesCode":"duck","category":"spuh","taxonOrder":500.0}
+    ]"#;
+
+    #[tokio::test]
+    async fn serves_the_taxonomy() {
+        let local = LocalSpecies::new(Arc::new(TaxonomyIndex::from_json(TAXA).unwrap()));
+
+        let filter = BirdFilter {
+            count: 5,
+            family: Some("thrush".to_owned()),
+            seed: Some(7),
+            ..BirdFilter::default()
+        };
+        let thrushes = local.random_birds("US-NY", &filter).await.unwrap();
+        let mut codes: Vec<&str> = thrushes.iter().map(|b| b.species_code.as_str()).collect();
+        codes.sort();
+        assert_eq!(codes, vec!["amerob", "herthr"]);
+
+        let filter = BirdFilter {
+            family: Some("heron".to_owned()),
+            ..BirdFilter::default()
+        };
+        assert!(matches!(
+            local.random_birds("US-NY", &filter).await,
+            Err(ProviderError::NotFound)
+        ));
+
+        // the same all day, and never a spuh
+        let day = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let today = local.bird_of_the_day("US-NY", day, &[]).await.unwrap();
+        assert_eq!(
+            local
+                .bird_of_the_day("US-NY", day, &[])
+                .await
+                .unwrap()
+                .species_code,
+            today.species_code
+        );
+        assert_eq!(today.category, "species");
+
+        // skips the excluded birds while there are others left
+        let exclude = vec![today.species_code.clone()];
+        let other = local.bird_of_the_day("US-NY", day, &exclude).await.unwrap();
+        assert_ne!(other.species_code, today.species_code);
+        let everything: Vec<String> = ["amerob", "herthr", "mallar3"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert!(local
+            .bird_of_the_day("US-NY", day, &everything)
+            .await
+            .is_ok());
+
+        assert_eq!(local.species("AMRO").await.unwrap().name, "American Robin");
+        assert!(local.species("zzzzzz").await.is_err());
+        assert_eq!(
+            local.search("mall", 5).await.unwrap()[0].species_code,
+            "mallar3"
+        );
+    }
+}
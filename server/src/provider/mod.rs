@@ -0,0 +1,168 @@
+use birdme_types::{Image, SearchResult};
+use chrono::NaiveDate;
+use std::fmt;
+
+use crate::api::ebird::{Bird, BirdFilter, EbirdError};
+use crate::api::wiki::WikiError;
+use crate::taxonomy::SearchMatch;
+
+pub mod local;
+
+// SpeciesProvider is where the routes get birds from. Birds are identified by
+// eBird species codes whatever the source, since sightings, frequency and the
+// bird of the day history are all keyed on them
+#[rocket::async_trait]
+pub trait SpeciesProvider: Send + Sync {
+    // random_birds picks filter.count birds found in region
+    async fn random_birds(
+        &self,
+        region: &str,
+        filter: &BirdFilter,
+    ) -> Result<Vec<Bird>, ProviderError>;
+
+    // bird_of_the_day picks the one bird everyone asking about region on date
+    // gets, passing over the codes in exclude unless nothing else is left.
+    // Remembering the pick so it stays put all day is up to History
+    async fn bird_of_the_day(
+        &self,
+        region: &str,
+        date: NaiveDate,
+        exclude: &[String],
+    ) -> Result<Bird, ProviderError>;
+
+    // species looks up one bird by species code, banding code or name
+    async fn species(&self, query: &str) -> Result<Bird, ProviderError>;
+
+    // search finds up to limit birds matching query, best first
+    async fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>, ProviderError>;
+
+    // localize swaps the birds' common names for their names in lang where
+    // the source has them. Sources without translations leave them be
+    async fn localize(&self, _birds: &mut [Bird], _lang: &str) {}
+}
+
+// DescriptionProvider is where the routes get a bird's blurb, picture and
+// link to read more from
+#[rocket::async_trait]
+pub trait DescriptionProvider: Send + Sync {
+    // describe looks up a bird by its common name in lang and its scientific
+    // name
+    async fn describe(
+        &self,
+        name: &str,
+        scientific_name: &str,
+        lang: &str,
+    ) -> Result<Description, ProviderError>;
+
+    // describe_many looks up every (name, scientific name) pair, with results
+    // lining up with birds. One at a time unless the provider can do better
+    async fn describe_many(
+        &self,
+        birds: &[(String, String)],
+        lang: &str,
+    ) -> Vec<Result<Description, ProviderError>> {
+        let mut descriptions = Vec::with_capacity(birds.len());
+        for (name, scientific_name) in birds {
+            descriptions.push(self.describe(name, scientific_name, lang).await);
+        }
+
+        descriptions
+    }
+}
+
+// Description is what a DescriptionProvider knows about a bird, matching the
+// optional fields of the Bird handed to clients
+pub struct Description {
+    // page to read more on
+    pub link: Option<String>,
+    // plain text, paragraphs split by a blank line
+    pub blurb: String,
+    // the blurb as html, when the source has it
+    pub blurb_html: Option<String>,
+    // one line summary, e.g. "Species of bird"
+    pub description: Option<String>,
+    pub image: Option<Image>,
+}
+
+// NoDescriptions leaves birds without blurbs, e.g. to run without Wikimedia
+// credentials
+pub struct NoDescriptions;
+
+#[rocket::async_trait]
+impl DescriptionProvider for NoDescriptions {
+    async fn describe(&self, _: &str, _: &str, _: &str) -> Result<Description, ProviderError> {
+        Err(ProviderError::Disabled(
+            "descriptions are turned off".to_owned(),
+        ))
+    }
+}
+
+#[derive(Debug)]
+pub enum ProviderError {
+    // the source couldn't be reached or took too long
+    Unavailable(String),
+    // the source is switched off, e.g. running offline. Nothing to log
+    Disabled(String),
+    // the source turned down what it was asked, e.g. a region it doesn't know
+    BadRequest(String),
+    // the source answered with something we couldn't use
+    BadResponse(String),
+    // nothing matched, e.g. no species in the region or none by that name
+    NotFound,
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProviderError::Unavailable(e)
+            | ProviderError::Disabled(e)
+            | ProviderError::BadRequest(e)
+            | ProviderError::BadResponse(e) => write!(f, "{}", e),
+            ProviderError::NotFound => write!(f, "nothing matched"),
+        }
+    }
+}
+
+impl From<EbirdError> for ProviderError {
+    fn from(err: EbirdError) -> Self {
+        match err {
+            EbirdError::Transport(_) => ProviderError::Unavailable(err.to_string()),
+            EbirdError::Offline => ProviderError::Disabled(err.to_string()),
+            // eBird rejects codes it doesn't know with a 400
+            EbirdError::Status(400) => ProviderError::BadRequest(err.to_string()),
+            EbirdError::Status(_) | EbirdError::Decode(_) => {
+                ProviderError::BadResponse(err.to_string())
+            }
+            EbirdError::Empty => ProviderError::NotFound,
+        }
+    }
+}
+
+impl From<WikiError> for ProviderError {
+    fn from(err: WikiError) -> Self {
+        match err {
+            WikiError::Transport(_) | WikiError::Timeout => {
+                ProviderError::Unavailable(err.to_string())
+            }
+            WikiError::Offline => ProviderError::Disabled(err.to_string()),
+            WikiError::Status(_) | WikiError::Decode(_) => {
+                ProviderError::BadResponse(err.to_string())
+            }
+            WikiError::NoResults(_) => ProviderError::NotFound,
+        }
+    }
+}
+
+// to_search_result turns a taxonomy match into what the search route hands
+// back, for providers searching a TaxonomyIndex
+pub fn to_search_result(m: SearchMatch) -> SearchResult {
+    SearchResult {
+        name: m.taxon.com_name.clone(),
+        scientific_name: m.taxon.sci_name.clone(),
+        species_code: m.taxon.species_code.clone(),
+        family_name: m.taxon.family_com_name.clone(),
+        banding_codes: m.taxon.banding_codes.clone(),
+        matched: m.field.to_owned(),
+        score: m.score,
+    }
+}
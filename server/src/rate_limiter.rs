@@ -15,23 +15,21 @@ impl RateLimiter {
         }
     }
 
-    // can_request returns true when the current IP isn't hitting the set
-    // rate limit
-    pub fn can_request(&self, ip: String) -> bool {
+    // can_request returns Ok when the current IP isn't hitting the set rate
+    // limit, otherwise the number of seconds left until it can try again
+    pub fn can_request(&self, ip: String) -> Result<(), i64> {
         let now = chrono::Utc::now();
 
-        match self.map.read().expect("reading from the map").get(&ip) {
-            Some(t) => {
-                let upper_time_limit = *t + chrono::Duration::seconds(self.duration);
-                if now.le(&upper_time_limit) {
-                    return false;
-                }
+        if let Some(t) = self.map.read().expect("reading from the map").get(&ip) {
+            let upper_time_limit = *t + chrono::Duration::seconds(self.duration);
+            if now.le(&upper_time_limit) {
+                // round up so a client waiting exactly this long gets through
+                return Err((upper_time_limit - now).num_seconds() + 1);
             }
-            None => {}
         }
 
         let mut lock = self.map.write().expect("locking the hash map to write");
         lock.insert(ip, now);
-        true
+        Ok(())
     }
 }
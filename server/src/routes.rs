@@ -1,68 +1,696 @@
+use birdme_types::{
+    Bird, BirdsQuery, CacheCleared, Frequency, Hotspot, Observation, Region, SearchResult,
+    Sightings, SpeciesDetail, TodaysBird,
+};
+use chrono::Utc;
 use core::net::IpAddr;
-use rocket::serde::{json::Json, Serialize};
+use rocket::serde::json::Json;
 use rocket::State;
+use server::admin::Admin;
+use server::api::ebird::{
+    self, is_valid_loc_id, is_valid_region, BirdFilter, EbirdError, RegionType,
+};
+use server::error::ApiError;
+use server::lang::Lang;
+use server::provider::{Description, ProviderError};
 use server::rate_limiter::RateLimiter;
 
 use crate::config::ServiceConfig;
 
-#[derive(Serialize)]
-#[serde(crate = "rocket::serde")]
-pub struct Bird {
-    pub name: String,
-    pub scientific_name: String,
-    pub link: String,
-    pub blurb: String,
+// every bird handed out costs a Wikipedia lookup, so keep it to a handful
+const MAX_BIRDS: usize = 20;
+
+// to_filter checks the query for the random birds route and turns it into
+// what the species provider picks by
+fn to_filter(query: BirdsQuery) -> Result<BirdFilter, ApiError> {
+    let mut filter = BirdFilter::default();
+
+    if let Some(count) = query.count {
+        if count == 0 || count > MAX_BIRDS {
+            return Err(ApiError::BadQuery(format!(
+                "count must be between 1 and {}",
+                MAX_BIRDS
+            )));
+        }
+        filter.count = count;
+    }
+
+    if let Some(category) = &query.category {
+        if !ebird::CATEGORIES.contains(&category.to_lowercase().as_str()) {
+            return Err(ApiError::BadQuery(format!(
+                "category must be one of {}",
+                ebird::CATEGORIES.join(", ")
+            )));
+        }
+    }
+
+    if let Some(rarity) = query.rarity {
+        if !(0.0..=1.0).contains(&rarity) {
+            return Err(ApiError::BadQuery(
+                "rarity must be between 0 and 1".to_owned(),
+            ));
+        }
+    }
+    filter.rarity = match (query.weighted, query.rarity) {
+        (_, Some(rarity)) => Some(rarity),
+        (Some(true), None) => Some(0.0),
+        _ => None,
+    };
+
+    filter.family = query.family.filter(|f| !f.trim().is_empty());
+    filter.category = query.category;
+    filter.seed = query.seed;
+
+    Ok(filter)
 }
 
-#[get("/birds/<region>")]
+#[get("/birds/<region>?<query..>")]
 pub async fn get_birds(
     config: &State<ServiceConfig>,
     limiter: &State<RateLimiter>,
     ip: IpAddr,
+    lang: Result<Lang, ApiError>,
+    region: &str,
+    query: BirdsQuery,
+) -> Result<Json<Vec<Bird>>, ApiError> {
+    let ip = ip.to_string();
+    limiter.can_request(ip).map_err(ApiError::RateLimited)?;
+    let lang = lang?;
+
+    if !is_valid_region(region) {
+        return Err(ApiError::BadRegion(region.to_owned()));
+    }
+    let filter = to_filter(query)?;
+
+    let mut birds = config
+        .species
+        .random_birds(region, &filter)
+        .await
+        .map_err(|e| match (e, &filter.family) {
+            (ProviderError::NotFound, Some(family)) => {
+                ApiError::NotFound(format!("no {} species found in {}", family, region))
+            }
+            (e, _) => ApiError::from_provider(e, region),
+        })?;
+
+    config.species.localize(&mut birds, lang.code()).await;
+
+    let names: Vec<(String, String)> = birds
+        .iter()
+        .map(|b| (b.name.clone(), b.scientific_name.clone()))
+        .collect();
+    let descriptions = config.descriptions.describe_many(&names, lang.code()).await;
+
+    // a bird is still worth showing without its blurb
+    Ok(Json(
+        birds
+            .into_iter()
+            .zip(descriptions)
+            .map(|(bird, description)| {
+                let description = description_of(&bird.name, description);
+                to_bird(bird, description)
+            })
+            .collect(),
+    ))
+}
+
+// get_today hands back the region's bird of the day. Days follow UTC so
+// everyone sees the same bird no matter where they're asking from
+#[get("/birds/<region>/today")]
+pub async fn get_today(
+    config: &State<ServiceConfig>,
+    limiter: &State<RateLimiter>,
+    ip: IpAddr,
+    lang: Result<Lang, ApiError>,
+    region: &str,
+) -> Result<Json<TodaysBird>, ApiError> {
+    let ip = ip.to_string();
+    limiter.can_request(ip).map_err(ApiError::RateLimited)?;
+    let lang = lang?;
+
+    if !is_valid_region(region) {
+        return Err(ApiError::BadRegion(region.to_owned()));
+    }
+
+    let date = Utc::now().date_naive();
+    let mut bird = config
+        .history
+        .bird_of_the_day(config.species.as_ref(), region, date)
+        .await
+        .map_err(|e| ApiError::from_provider(e, region))?;
+    config
+        .species
+        .localize(std::slice::from_mut(&mut bird), lang.code())
+        .await;
+
+    let description = description_of(
+        &bird.name,
+        config
+            .descriptions
+            .describe(&bird.name, &bird.scientific_name, lang.code())
+            .await,
+    );
+
+    Ok(Json(TodaysBird {
+        date: date.format("%Y-%m-%d").to_string(),
+        bird: to_bird(bird, description),
+    }))
+}
+
+// how far back species sightings go when the caller doesn't say, matching
+// eBird's own default
+const DEFAULT_BACK_DAYS: u8 = 14;
+
+// get_species looks up one bird by species code, banding code or (possibly
+// misspelled) name. Passing a region adds a summary of recent sightings there
+#[get("/species/<query>?<region>&<back>")]
+pub async fn get_species(
+    config: &State<ServiceConfig>,
+    limiter: &State<RateLimiter>,
+    ip: IpAddr,
+    lang: Result<Lang, ApiError>,
+    query: &str,
+    region: Option<&str>,
+    back: Option<u8>,
+) -> Result<Json<SpeciesDetail>, ApiError> {
+    let ip = ip.to_string();
+    limiter.can_request(ip).map_err(ApiError::RateLimited)?;
+    let lang = lang?;
+
+    if let Some(r) = region {
+        if !is_valid_region(r) {
+            return Err(ApiError::BadRegion(r.to_owned()));
+        }
+    }
+    check_observation_query(back, None)?;
+
+    let mut bird = config.species.species(query).await.map_err(|e| match e {
+        ProviderError::NotFound | ProviderError::BadRequest(_) => {
+            ApiError::NotFound(format!("no species matches {}", query))
+        }
+        e => e.into(),
+    })?;
+
+    let sightings = match region {
+        Some(r) => {
+            let days = back.unwrap_or(DEFAULT_BACK_DAYS);
+            let obs = config
+                .ebird
+                .species_observations(r, &bird.species_code, Some(days))
+                .await
+                .map_err(|e| ApiError::from_ebird(e, r))?;
+
+            Some(Sightings {
+                region: r.to_owned(),
+                days,
+                locations: obs.len(),
+                birds_counted: obs.iter().filter_map(|o| o.count).sum(),
+                // the dates sort as text since they're all YYYY-MM-DD HH:MM
+                latest: obs.into_iter().map(|o| o.date).max(),
+            })
+        }
+        None => None,
+    };
+
+    config
+        .species
+        .localize(std::slice::from_mut(&mut bird), lang.code())
+        .await;
+
+    // the taxonomy alone is still worth returning without a description
+    let description = description_of(
+        &bird.name,
+        config
+            .descriptions
+            .describe(&bird.name, &bird.scientific_name, lang.code())
+            .await,
+    );
+
+    Ok(Json(SpeciesDetail {
+        bird: to_bird(bird, description),
+        sightings,
+    }))
+}
+
+const DEFAULT_SEARCH_RESULTS: usize = 20;
+const MAX_SEARCH_RESULTS: usize = 100;
+
+// get_search finds species whose names, banding codes or family match q,
+// allowing for partial names and typos. Best matches come first
+#[get("/search?<q>&<limit>")]
+pub async fn get_search(
+    config: &State<ServiceConfig>,
+    limiter: &State<RateLimiter>,
+    ip: IpAddr,
+    q: &str,
+    limit: Option<usize>,
+) -> Result<Json<Vec<SearchResult>>, ApiError> {
+    let ip = ip.to_string();
+    limiter.can_request(ip).map_err(ApiError::RateLimited)?;
+
+    if q.trim().is_empty() {
+        return Err(ApiError::BadQuery("q can't be empty".to_owned()));
+    }
+    let limit = limit.unwrap_or(DEFAULT_SEARCH_RESULTS);
+    if limit == 0 || limit > MAX_SEARCH_RESULTS {
+        return Err(ApiError::BadQuery(format!(
+            "limit must be between 1 and {}",
+            MAX_SEARCH_RESULTS
+        )));
+    }
+
+    Ok(Json(config.species.search(q, limit).await?))
+}
+
+// get_frequency shows which weeks of the year a species turns up in a region,
+// the data behind a bar chart
+#[get("/species/<query>/frequency?<region>")]
+pub async fn get_frequency(
+    config: &State<ServiceConfig>,
+    limiter: &State<RateLimiter>,
+    ip: IpAddr,
+    query: &str,
+    region: &str,
+) -> Result<Json<Frequency>, ApiError> {
+    let ip = ip.to_string();
+    limiter.can_request(ip).map_err(ApiError::RateLimited)?;
+
+    if !is_valid_region(region) {
+        return Err(ApiError::BadRegion(region.to_owned()));
+    }
+
+    let bird = config.species.species(query).await.map_err(|e| match e {
+        ProviderError::NotFound | ProviderError::BadRequest(_) => {
+            ApiError::NotFound(format!("no species matches {}", query))
+        }
+        e => e.into(),
+    })?;
+
+    let frequency = config
+        .ebird
+        .frequency(region, &bird.species_code, config.frequency)
+        .await
+        .map_err(|e| ApiError::from_ebird(e, region))?;
+
+    Ok(Json(Frequency {
+        species_code: bird.species_code,
+        name: bird.name,
+        region: region.to_owned(),
+        days_per_week: frequency.samples_per_week * frequency.years.len() as u32,
+        years: frequency.years,
+        samples_per_week: frequency.samples_per_week,
+        weeks: frequency.weeks,
+    }))
+}
+
+// eBird only keeps recent observations for the last 30 days
+const MAX_BACK_DAYS: u8 = 30;
+const MAX_RESULTS: u32 = 10000;
+
+#[get("/birds/<region>/recent?<back>&<max_results>")]
+pub async fn get_recent(
+    config: &State<ServiceConfig>,
+    limiter: &State<RateLimiter>,
+    ip: IpAddr,
+    lang: Result<Lang, ApiError>,
+    region: &str,
+    back: Option<u8>,
+    max_results: Option<u32>,
+) -> Result<Json<Vec<Observation>>, ApiError> {
+    let ip = ip.to_string();
+    limiter.can_request(ip).map_err(ApiError::RateLimited)?;
+    let lang = lang?;
+
+    if !is_valid_region(region) {
+        return Err(ApiError::BadRegion(region.to_owned()));
+    }
+    check_observation_query(back, max_results)?;
+
+    let obs = config
+        .ebird
+        .recent_observations(region, back, max_results, lang.code())
+        .await
+        .map_err(|e| ApiError::from_ebird(e, region))?;
+
+    Ok(Json(obs.into_iter().map(to_observation).collect()))
+}
+
+#[get("/birds/<region>/notable?<back>&<max_results>")]
+pub async fn get_notable(
+    config: &State<ServiceConfig>,
+    limiter: &State<RateLimiter>,
+    ip: IpAddr,
+    lang: Result<Lang, ApiError>,
     region: &str,
-) -> Option<Json<Vec<Bird>>> {
+    back: Option<u8>,
+    max_results: Option<u32>,
+) -> Result<Json<Vec<Observation>>, ApiError> {
     let ip = ip.to_string();
-    if !limiter.can_request(ip) {
-        // TODO can change this into an error type for better UX
-        return None;
+    limiter.can_request(ip).map_err(ApiError::RateLimited)?;
+    let lang = lang?;
+
+    if !is_valid_region(region) {
+        return Err(ApiError::BadRegion(region.to_owned()));
     }
+    check_observation_query(back, max_results)?;
+
+    let obs = config
+        .ebird
+        .notable_observations(region, back, max_results, lang.code())
+        .await
+        .map_err(|e| ApiError::from_ebird(e, region))?;
 
-    let birds = config.ebird.get_birds(region).await;
+    Ok(Json(obs.into_iter().map(to_observation).collect()))
+}
 
-    let mut r_birds: Vec<Bird> = vec![];
-    for bird in birds {
-        let link = format_link(&bird.name);
+// eBird caps how far out it will look around a point
+const MAX_OBSERVATION_DIST_KM: u16 = 50;
+const MAX_HOTSPOT_DIST_KM: u16 = 500;
 
-        let wiki_info = config.wiki.get(&bird.name).await;
-        match wiki_info {
-            Ok(info) => {
-                let b = Bird {
-                    name: bird.name.clone(),
-                    scientific_name: bird.scientific_name.clone(),
-                    link: link.clone(),
-                    blurb: info.snippet,
-                };
+// GeoQuery is a point to search around, with an optional radius in km
+#[derive(FromForm)]
+pub struct GeoQuery {
+    lat: f64,
+    lng: f64,
+    dist: Option<u16>,
+}
 
-                r_birds.push(b);
+impl GeoQuery {
+    fn check(&self, max_dist: u16) -> Result<(), ApiError> {
+        if !(-90.0..=90.0).contains(&self.lat) {
+            return Err(ApiError::BadQuery(
+                "lat must be between -90 and 90".to_owned(),
+            ));
+        }
+        if !(-180.0..=180.0).contains(&self.lng) {
+            return Err(ApiError::BadQuery(
+                "lng must be between -180 and 180".to_owned(),
+            ));
+        }
+        if let Some(d) = self.dist {
+            if d > max_dist {
+                return Err(ApiError::BadQuery(format!(
+                    "dist must be at most {} km",
+                    max_dist
+                )));
             }
-            Err(e) => {
-                println!(
-                    "Unable to get the wiki info for {}: {}",
-                    bird.name, e.message
-                );
+        }
+
+        Ok(())
+    }
+}
+
+#[get("/birds/near?<back>&<max_results>&<geo..>")]
+pub async fn get_nearby(
+    config: &State<ServiceConfig>,
+    limiter: &State<RateLimiter>,
+    ip: IpAddr,
+    lang: Result<Lang, ApiError>,
+    geo: GeoQuery,
+    back: Option<u8>,
+    max_results: Option<u32>,
+) -> Result<Json<Vec<Observation>>, ApiError> {
+    let ip = ip.to_string();
+    limiter.can_request(ip).map_err(ApiError::RateLimited)?;
+    let lang = lang?;
+
+    geo.check(MAX_OBSERVATION_DIST_KM)?;
+    check_observation_query(back, max_results)?;
+
+    let obs = config
+        .ebird
+        .nearby_observations(geo.lat, geo.lng, geo.dist, back, max_results, lang.code())
+        .await
+        .map_err(ApiError::from)?;
+
+    Ok(Json(obs.into_iter().map(to_observation).collect()))
+}
+
+#[get("/hotspots/near?<geo..>")]
+pub async fn get_nearby_hotspots(
+    config: &State<ServiceConfig>,
+    limiter: &State<RateLimiter>,
+    ip: IpAddr,
+    geo: GeoQuery,
+) -> Result<Json<Vec<Hotspot>>, ApiError> {
+    let ip = ip.to_string();
+    limiter.can_request(ip).map_err(ApiError::RateLimited)?;
+
+    geo.check(MAX_HOTSPOT_DIST_KM)?;
+
+    let spots = config
+        .ebird
+        .nearby_hotspots(geo.lat, geo.lng, geo.dist)
+        .await
+        .map_err(ApiError::from)?;
+
+    Ok(Json(spots.into_iter().map(to_hotspot).collect()))
+}
+
+#[get("/hotspots/<region>?<back>")]
+pub async fn get_hotspots(
+    config: &State<ServiceConfig>,
+    limiter: &State<RateLimiter>,
+    ip: IpAddr,
+    region: &str,
+    back: Option<u8>,
+) -> Result<Json<Vec<Hotspot>>, ApiError> {
+    let ip = ip.to_string();
+    limiter.can_request(ip).map_err(ApiError::RateLimited)?;
+
+    if !is_valid_region(region) {
+        return Err(ApiError::BadRegion(region.to_owned()));
+    }
+    check_observation_query(back, None)?;
+
+    let spots = config
+        .ebird
+        .region_hotspots(region, back)
+        .await
+        .map_err(|e| ApiError::from_ebird(e, region))?;
+
+    Ok(Json(spots.into_iter().map(to_hotspot).collect()))
+}
+
+#[get("/hotspots/info/<loc_id>")]
+pub async fn get_hotspot(
+    config: &State<ServiceConfig>,
+    limiter: &State<RateLimiter>,
+    ip: IpAddr,
+    loc_id: &str,
+) -> Result<Json<Hotspot>, ApiError> {
+    let ip = ip.to_string();
+    limiter.can_request(ip).map_err(ApiError::RateLimited)?;
+
+    if !is_valid_loc_id(loc_id) {
+        return Err(ApiError::BadQuery(format!(
+            "{} is not an eBird location id",
+            loc_id
+        )));
+    }
+
+    let spot = config
+        .ebird
+        .hotspot_info(loc_id)
+        .await
+        .map_err(|e| match e {
+            EbirdError::Status(400) | EbirdError::Status(404) => {
+                ApiError::NotFound(format!("no hotspot with id {}", loc_id))
             }
+            e => e.into(),
+        })?;
+
+    Ok(Json(to_hotspot(spot)))
+}
+
+// RegionQuery filters the region list. parent defaults to the whole world and
+// type defaults to the level right below parent
+#[derive(FromForm)]
+pub struct RegionQuery {
+    parent: Option<String>,
+    #[field(name = "type")]
+    kind: Option<String>,
+    // case insensitive search on the region name
+    q: Option<String>,
+}
+
+#[get("/regions?<query..>")]
+pub async fn get_regions(
+    config: &State<ServiceConfig>,
+    limiter: &State<RateLimiter>,
+    ip: IpAddr,
+    query: RegionQuery,
+) -> Result<Json<Vec<Region>>, ApiError> {
+    let ip = ip.to_string();
+    limiter.can_request(ip).map_err(ApiError::RateLimited)?;
+
+    let parent = query.parent.as_deref().unwrap_or(ebird::WORLD);
+    if parent != ebird::WORLD && !is_valid_region(parent) {
+        return Err(ApiError::BadRegion(parent.to_owned()));
+    }
+
+    let region_type = match &query.kind {
+        Some(kind) => RegionType::parse(kind).ok_or_else(|| {
+            ApiError::BadQuery(
+                "type must be one of country, subnational1 or subnational2".to_owned(),
+            )
+        })?,
+        None => RegionType::below(parent)
+            .ok_or_else(|| ApiError::BadQuery(format!("{} has no regions below it", parent)))?,
+    };
+
+    let regions = config
+        .ebird
+        .regions(region_type, parent)
+        .await
+        .map_err(|e| ApiError::from_ebird(e, parent))?;
+
+    let needle = query.q.map(|q| q.to_lowercase());
+    Ok(Json(
+        regions
+            .into_iter()
+            .filter(|r| match &needle {
+                Some(n) => r.name.to_lowercase().contains(n),
+                None => true,
+            })
+            .map(|r| Region {
+                code: r.code,
+                name: r.name,
+            })
+            .collect(),
+    ))
+}
+
+#[get("/regions/<code>")]
+pub async fn get_region(
+    config: &State<ServiceConfig>,
+    limiter: &State<RateLimiter>,
+    ip: IpAddr,
+    code: &str,
+) -> Result<Json<Region>, ApiError> {
+    let ip = ip.to_string();
+    limiter.can_request(ip).map_err(ApiError::RateLimited)?;
+
+    if !is_valid_region(code) {
+        return Err(ApiError::BadRegion(code.to_owned()));
+    }
+
+    let region = config
+        .ebird
+        .region_info(code)
+        .await
+        .map_err(|e| ApiError::from_ebird(e, code))?;
+
+    Ok(Json(Region {
+        code: region.code,
+        name: region.name,
+    }))
+}
+
+// invalidate_cache drops cached species lists, taxonomy, historic days and
+// translated names, e.g. after eBird publishes its yearly taxonomy update.
+// prefix narrows it down to one kind ("spplist:", "taxonomy:", "historic:" or
+// "names:") or a single entry ("spplist:US-NY")
+#[delete("/admin/cache?<prefix>")]
+pub async fn invalidate_cache(
+    config: &State<ServiceConfig>,
+    admin: Result<Admin, ApiError>,
+    prefix: Option<&str>,
+) -> Result<Json<CacheCleared>, ApiError> {
+    admin?;
+
+    Ok(Json(CacheCleared {
+        removed: config.ebird.invalidate_cache(prefix),
+    }))
+}
+
+// check_observation_query keeps back and max_results inside the ranges eBird
+// accepts so we can answer with a 400 instead of passing its error along
+fn check_observation_query(back: Option<u8>, max_results: Option<u32>) -> Result<(), ApiError> {
+    if let Some(b) = back {
+        if b == 0 || b > MAX_BACK_DAYS {
+            return Err(ApiError::BadQuery(format!(
+                "back must be between 1 and {} days",
+                MAX_BACK_DAYS
+            )));
+        }
+    }
+    if let Some(m) = max_results {
+        if m == 0 || m > MAX_RESULTS {
+            return Err(ApiError::BadQuery(format!(
+                "max_results must be between 1 and {}",
+                MAX_RESULTS
+            )));
         }
     }
 
-    Some(Json(r_birds))
+    Ok(())
 }
 
-// format_link generates a wiki link by taking the common name of the
-// bird and replacing spaces with underscores
-fn format_link(bird_name: &str) -> String {
-    let base = "https://en.wikipedia.org/wiki/";
-    let mut name_portion = String::from(bird_name);
-    name_portion = name_portion.replace(" ", "_");
+// description_of unwraps a description lookup, logging why there isn't one
+fn description_of(
+    name: &str,
+    description: Result<Description, ProviderError>,
+) -> Option<Description> {
+    match description {
+        Ok(d) => Some(d),
+        // offline demos go without, that's expected
+        Err(ProviderError::Disabled(_)) => None,
+        Err(e) => {
+            println!("Unable to get the description for {}: {}", name, e);
+            None
+        }
+    }
+}
 
-    format!("{}{}", base, name_portion)
+fn to_bird(bird: ebird::Bird, description: Option<Description>) -> Bird {
+    let (link, blurb, blurb_html, description, image) = match description {
+        Some(d) => (d.link, Some(d.blurb), d.blurb_html, d.description, d.image),
+        None => (None, None, None, None, None),
+    };
+
+    Bird {
+        name: bird.name,
+        scientific_name: bird.scientific_name,
+        species_code: bird.species_code,
+        taxon_order: bird.taxon_order,
+        order: bird.order,
+        family_name: bird.family_name,
+        family_scientific_name: bird.family_scientific_name,
+        category: bird.category,
+        banding_codes: bird.banding_codes,
+        link,
+        blurb,
+        blurb_html,
+        description,
+        image,
+    }
+}
+
+fn to_observation(obs: ebird::Observation) -> Observation {
+    Observation {
+        species_code: obs.species_code,
+        name: obs.name,
+        scientific_name: obs.scientific_name,
+        count: obs.count,
+        location: obs.location,
+        lat: obs.lat,
+        lng: obs.lng,
+        observed_on: obs.date,
+        valid: obs.valid,
+        reviewed: obs.reviewed,
+    }
+}
+
+fn to_hotspot(spot: ebird::Hotspot) -> Hotspot {
+    Hotspot {
+        id: spot.id,
+        name: spot.name,
+        country: spot.country,
+        subnational1: spot.subnational1,
+        lat: spot.lat,
+        lng: spot.lng,
+        species_count: spot.species_count,
+        latest_observation: spot.latest_observation,
+    }
 }
@@ -0,0 +1,25 @@
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+// sled lets go of its lock on a store from a background thread after the last
+// handle is dropped, so a store closed a moment ago (a quick restart, or a
+// test reopening it) can still look taken. Waits up to about a second
+const OPEN_ATTEMPTS: u32 = 50;
+const OPEN_RETRY_DELAY: Duration = Duration::from_millis(20);
+
+// open opens the sled store at path for the cache or the history. A held
+// lock comes back as a plain io error with only its message to tell it apart,
+// so any io error gets a few more tries before it's handed back
+pub fn open(path: &Path) -> sled::Result<sled::Db> {
+    let mut attempt = 1;
+    loop {
+        match sled::open(path) {
+            Err(sled::Error::Io(_)) if attempt < OPEN_ATTEMPTS => {
+                thread::sleep(OPEN_RETRY_DELAY);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
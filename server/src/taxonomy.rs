@@ -0,0 +1,491 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// Taxon is a single entry of the eBird taxonomy, shaped like the JSON that
+// ref/taxonomy/ebird returns. eBird leaves some of these out for spuhs,
+// slashes and hybrids, so everything but the names and code is optional
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Taxon {
+    pub sci_name: String,
+    pub com_name: String,
+    pub species_code: String,
+    #[serde(default)]
+    pub category: String,
+    #[serde(default)]
+    pub taxon_order: f32,
+    #[serde(default)]
+    pub banding_codes: Vec<String>,
+    #[serde(default)]
+    pub com_name_codes: Vec<String>,
+    #[serde(default)]
+    pub sci_name_codes: Vec<String>,
+    #[serde(default)]
+    pub order: String,
+    #[serde(default)]
+    pub family_code: String,
+    #[serde(default)]
+    pub family_com_name: String,
+    #[serde(default)]
+    pub family_sci_name: String,
+}
+
+// the CSV flavor of the taxonomy, as downloaded from eBird without fmt=json.
+// The code columns hold space separated lists
+#[derive(Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+struct CsvRow {
+    scientific_name: String,
+    common_name: String,
+    species_code: String,
+    #[serde(default)]
+    category: String,
+    #[serde(default)]
+    taxon_order: Option<f32>,
+    #[serde(default)]
+    com_name_codes: String,
+    #[serde(default)]
+    sci_name_codes: String,
+    #[serde(default)]
+    banding_codes: String,
+    #[serde(default)]
+    order: String,
+    #[serde(default)]
+    family_code: String,
+    #[serde(default)]
+    family_com_name: String,
+    #[serde(default)]
+    family_sci_name: String,
+}
+
+impl From<CsvRow> for Taxon {
+    fn from(row: CsvRow) -> Self {
+        let codes = |s: &str| s.split_whitespace().map(String::from).collect();
+
+        Self {
+            sci_name: row.scientific_name,
+            com_name: row.common_name,
+            species_code: row.species_code,
+            category: row.category,
+            taxon_order: row.taxon_order.unwrap_or_default(),
+            banding_codes: codes(&row.banding_codes),
+            com_name_codes: codes(&row.com_name_codes),
+            sci_name_codes: codes(&row.sci_name_codes),
+            order: row.order,
+            family_code: row.family_code,
+            family_com_name: row.family_com_name,
+            family_sci_name: row.family_sci_name,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum TaxonomyError {
+    // the file couldn't be read
+    Io(String),
+    // the file was read but isn't valid CSV or JSON taxonomy
+    Parse(String),
+    // the file extension is neither .csv nor .json
+    UnknownFormat(String),
+}
+
+impl fmt::Display for TaxonomyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TaxonomyError::Io(e) => write!(f, "unable to read the taxonomy file: {}", e),
+            TaxonomyError::Parse(e) => write!(f, "unable to parse the taxonomy file: {}", e),
+            TaxonomyError::UnknownFormat(p) => {
+                write!(f, "{} should end in .csv or .json", p)
+            }
+        }
+    }
+}
+
+// TaxonomyIndex holds the whole eBird taxonomy in memory so species can be
+// resolved without a round trip to eBird. Entries can be looked up by species
+// code, common name, scientific name or 4 letter banding code
+#[derive(Default)]
+pub struct TaxonomyIndex {
+    taxa: Vec<Taxon>,
+    by_code: HashMap<String, usize>,
+    by_common_name: HashMap<String, usize>,
+    by_scientific_name: HashMap<String, usize>,
+    by_banding_code: HashMap<String, usize>,
+}
+
+impl TaxonomyIndex {
+    pub fn new(mut taxa: Vec<Taxon>) -> Self {
+        taxa.sort_by(|a, b| a.taxon_order.total_cmp(&b.taxon_order));
+
+        let mut index = Self {
+            taxa: vec![],
+            by_code: HashMap::with_capacity(taxa.len()),
+            by_common_name: HashMap::with_capacity(taxa.len()),
+            by_scientific_name: HashMap::with_capacity(taxa.len()),
+            by_banding_code: HashMap::new(),
+        };
+
+        for (i, taxon) in taxa.iter().enumerate() {
+            index.by_code.insert(taxon.species_code.to_lowercase(), i);
+            index
+                .by_common_name
+                .insert(taxon.com_name.to_lowercase(), i);
+            index
+                .by_scientific_name
+                .insert(taxon.sci_name.to_lowercase(), i);
+            for code in &taxon.banding_codes {
+                index.by_banding_code.insert(code.to_uppercase(), i);
+            }
+        }
+        index.taxa = taxa;
+
+        index
+    }
+
+    // load reads an eBird taxonomy export, picking CSV or JSON from the file
+    // extension
+    pub fn load(path: &Path) -> Result<Self, TaxonomyError> {
+        let contents = fs::read_to_string(path).map_err(|e| TaxonomyError::Io(e.to_string()))?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Self::from_json(&contents),
+            Some("csv") => Self::from_csv(&contents),
+            _ => Err(TaxonomyError::UnknownFormat(path.display().to_string())),
+        }
+    }
+
+    pub fn from_json(contents: &str) -> Result<Self, TaxonomyError> {
+        let taxa: Vec<Taxon> =
+            serde_json::from_str(contents).map_err(|e| TaxonomyError::Parse(e.to_string()))?;
+
+        Ok(Self::new(taxa))
+    }
+
+    pub fn from_csv(contents: &str) -> Result<Self, TaxonomyError> {
+        let mut reader = csv::Reader::from_reader(contents.as_bytes());
+
+        let taxa = reader
+            .deserialize::<CsvRow>()
+            .map(|row| row.map(Taxon::from))
+            .collect::<Result<Vec<Taxon>, csv::Error>>()
+            .map_err(|e| TaxonomyError::Parse(e.to_string()))?;
+
+        Ok(Self::new(taxa))
+    }
+
+    pub fn len(&self) -> usize {
+        self.taxa.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.taxa.is_empty()
+    }
+
+    // iter walks the taxonomy in taxonomic order
+    pub fn iter(&self) -> impl Iterator<Item = &Taxon> {
+        self.taxa.iter()
+    }
+
+    pub fn by_code(&self, code: &str) -> Option<&Taxon> {
+        self.lookup(&self.by_code, &code.to_lowercase())
+    }
+
+    pub fn by_common_name(&self, name: &str) -> Option<&Taxon> {
+        self.lookup(&self.by_common_name, &name.to_lowercase())
+    }
+
+    pub fn by_scientific_name(&self, name: &str) -> Option<&Taxon> {
+        self.lookup(&self.by_scientific_name, &name.to_lowercase())
+    }
+
+    pub fn by_banding_code(&self, code: &str) -> Option<&Taxon> {
+        self.lookup(&self.by_banding_code, &code.to_uppercase())
+    }
+
+    // resolve turns whatever the user typed into a taxon: a species code,
+    // banding code, common or scientific name, and failing those the common
+    // name that's closest to it. Close means at most about a quarter of the
+    // letters need changing, so typos match but unrelated names don't
+    pub fn resolve(&self, query: &str) -> Option<&Taxon> {
+        let query = query.trim();
+        if query.is_empty() {
+            return None;
+        }
+
+        let exact = self
+            .by_code(query)
+            .or_else(|| self.by_banding_code(query))
+            .or_else(|| self.by_common_name(query))
+            .or_else(|| self.by_scientific_name(query));
+        if exact.is_some() {
+            return exact;
+        }
+
+        let needle = normalize(query);
+        let max_distance = needle.chars().count() / 4;
+
+        self.taxa
+            .iter()
+            .map(|t| (t, edit_distance(&needle, &normalize(&t.com_name))))
+            .filter(|(_, d)| *d <= max_distance)
+            .min_by_key(|(_, d)| *d)
+            .map(|(t, _)| t)
+    }
+
+    // search finds up to limit taxa matching query, best match first. Ties
+    // keep taxonomic order
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchMatch<'_>> {
+        let needle = normalize(query);
+        if needle.is_empty() {
+            return vec![];
+        }
+
+        let mut matches: Vec<SearchMatch> = self
+            .taxa
+            .iter()
+            .filter_map(|t| score(t, query.trim(), &needle))
+            .collect();
+        // the sort is stable, so equal scores stay in taxonomic order
+        matches.sort_by_key(|m| std::cmp::Reverse(m.score));
+        matches.truncate(limit);
+
+        matches
+    }
+
+    fn lookup(&self, map: &HashMap<String, usize>, key: &str) -> Option<&Taxon> {
+        map.get(key).map(|&i| &self.taxa[i])
+    }
+}
+
+// SearchMatch is a taxon that matched a search along with how well
+pub struct SearchMatch<'a> {
+    pub taxon: &'a Taxon,
+    // higher is better, see score for the scale
+    pub score: u32,
+    // which field matched, e.g. "banding_code" or "common_name"
+    pub field: &'static str,
+}
+
+// score rates how well a taxon matches the query, keeping the best of every
+// field that matches. Exact codes beat exact names, which beat prefixes, which
+// beat a match anywhere in the name, which beat family matches and typos
+fn score<'a>(taxon: &'a Taxon, raw: &str, needle: &str) -> Option<SearchMatch<'a>> {
+    let common = normalize(&taxon.com_name);
+    let scientific = normalize(&taxon.sci_name);
+    let upper = raw.to_uppercase();
+
+    let word_prefix = |name: &str| name.split(' ').any(|w| w.starts_with(needle));
+
+    let mut candidates: Vec<(u32, &'static str)> = vec![];
+    if taxon.banding_codes.contains(&upper) {
+        candidates.push((100, "banding_code"));
+    }
+    if taxon.species_code.eq_ignore_ascii_case(raw) {
+        candidates.push((100, "species_code"));
+    }
+    if taxon.com_name_codes.contains(&upper) {
+        candidates.push((90, "banding_code"));
+    }
+    if common == needle {
+        candidates.push((95, "common_name"));
+    }
+    if scientific == needle {
+        candidates.push((95, "scientific_name"));
+    }
+    if common.starts_with(needle) {
+        candidates.push((80, "common_name"));
+    }
+    if scientific.starts_with(needle) {
+        candidates.push((75, "scientific_name"));
+    }
+    if word_prefix(&common) {
+        candidates.push((70, "common_name"));
+    }
+    if word_prefix(&scientific) {
+        candidates.push((65, "scientific_name"));
+    }
+    if common.contains(needle) {
+        candidates.push((60, "common_name"));
+    }
+
+    let family = normalize(&taxon.family_com_name);
+    let family_sci = normalize(&taxon.family_sci_name);
+    if family == needle || family_sci == needle {
+        candidates.push((55, "family"));
+    }
+    if word_prefix(&family) || family_sci.starts_with(needle) {
+        candidates.push((45, "family"));
+    }
+
+    // typos only count against names, codes are too short to tell apart
+    let max_distance = needle.chars().count() / 4;
+    if max_distance > 0 {
+        let distance = std::iter::once(common.as_str())
+            .chain(common.split(' '))
+            .map(|w| edit_distance(needle, w))
+            .min()
+            .unwrap_or(usize::MAX);
+        if distance <= max_distance {
+            candidates.push((40 - 5 * distance.min(8) as u32, "common_name"));
+        }
+    }
+
+    candidates
+        .into_iter()
+        .max_by_key(|(s, _)| *s)
+        .map(|(score, field)| SearchMatch {
+            taxon,
+            score,
+            field,
+        })
+}
+
+// normalize lowercases a name and squashes punctuation and runs of spaces so
+// "Cooper's Hawk" and "coopers  hawk" compare the same
+pub fn normalize(name: &str) -> String {
+    name.to_lowercase()
+        .chars()
+        .map(|c| if c == '-' { ' ' } else { c })
+        .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+// edit_distance is the Levenshtein distance between two strings, the number
+// of single character inserts, deletes and swaps to get from one to the other
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        cur[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let swap = prev[j] + usize::from(ca != *cb);
+            cur[j + 1] = swap.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = "SCIENTIFIC_NAME,COMMON_NAME,SPECIES_CODE,CATEGORY,TAXON_ORDER,COM_NAME_CODES,SCI_NAME_CODES,BANDING_CODES,ORDER,FAMILY_COM_NAME,FAMILY_SCI_NAME,REPORT_AS,EXTINCT,EXTINCT_YEAR,FAMILY_CODE
+Turdus migratorius,American Robin,amerob,species,30419,AMRO,TUMI,AMRO,Passeriformes,Thrushes and Allies,Turdidae,,,,turdid1
+Anas platyrhynchos,Mallard,mallar3,species,316,MALL,ANPL,MALL,Anseriformes,\"Ducks, Geese, and Waterfowl\",Anatidae,,,,anatid1
+";
+
+    const JSON: &str = r#"[
+        {"sciName":"Turdus migratorius","comName":"American Robin","speciesCode":"amerob","category":"species","taxonOrder":30419.0,"bandingCodes":["AMRO"],"comNameCodes":["AMRO"],"sciNameCodes":["TUMI"],"order":"Passeriformes","familyCode":"turdid1","familyComName":"Thrushes and Allies","familySciName":"Turdidae"},
+        {"sciName":"Anas sp.","comName":"duck sp.","speciesCode":"duck","category":"spuh","taxonOrder":500.0}
+    ]"#;
+
+    #[test]
+    fn csv_lookups() {
+        let index = TaxonomyIndex::from_csv(CSV).unwrap();
+        assert_eq!(index.len(), 2);
+
+        assert_eq!(index.by_code("amerob").unwrap().com_name, "American Robin");
+        assert_eq!(
+            index.by_banding_code("amro").unwrap().species_code,
+            "amerob"
+        );
+        assert_eq!(
+            index
+                .by_scientific_name("anas platyrhynchos")
+                .unwrap()
+                .com_name,
+            "Mallard"
+        );
+        assert_eq!(
+            index.by_common_name("MALLARD").unwrap().family_com_name,
+            "Ducks, Geese, and Waterfowl"
+        );
+        assert!(index.by_code("nope").is_none());
+
+        // sorted by taxonomic order rather than file order
+        assert_eq!(index.iter().next().unwrap().species_code, "mallar3");
+    }
+
+    #[test]
+    fn resolve() {
+        let index = TaxonomyIndex::from_csv(CSV).unwrap();
+
+        let code = |q: &str| index.resolve(q).map(|t| t.species_code.as_str());
+        assert_eq!(code("amerob"), Some("amerob"));
+        assert_eq!(code("AMRO"), Some("amerob"));
+        assert_eq!(code("american robin"), Some("amerob"));
+        assert_eq!(code("Anas platyrhynchos"), Some("mallar3"));
+        assert_eq!(code("amercan robbin"), Some("amerob"));
+        assert_eq!(code("malard"), Some("mallar3"));
+        assert_eq!(code("blue jay"), None);
+        assert_eq!(code("  "), None);
+    }
+
+    #[test]
+    fn search_ranking() {
+        let index = TaxonomyIndex::from_json(
+            r#"[
+            {"sciName":"Turdus migratorius","comName":"American Robin","speciesCode":"amerob","taxonOrder":30419.0,"bandingCodes":["AMRO"],"familyComName":"Thrushes and Allies","familySciName":"Turdidae"},
+            {"sciName":"Erithacus rubecula","comName":"European Robin","speciesCode":"eurrob1","taxonOrder":29000.0,"familyComName":"Old World Flycatchers","familySciName":"Muscicapidae"},
+            {"sciName":"Catharus fuscescens","comName":"Veery","speciesCode":"veery","taxonOrder":30200.0,"bandingCodes":["VEER"],"familyComName":"Thrushes and Allies","familySciName":"Turdidae"},
+            {"sciName":"Spinus tristis","comName":"American Goldfinch","speciesCode":"amegfi","taxonOrder":33000.0,"bandingCodes":["AMGO"],"familyComName":"Finches, Euphonias, and Allies","familySciName":"Fringillidae"}
+        ]"#,
+        )
+        .unwrap();
+
+        let codes = |q: &str| -> Vec<String> {
+            index
+                .search(q, 10)
+                .iter()
+                .map(|m| m.taxon.species_code.clone())
+                .collect()
+        };
+
+        assert_eq!(codes("amro")[0], "amerob");
+        assert_eq!(codes("robin"), vec!["eurrob1", "amerob"]);
+        assert_eq!(codes("american"), vec!["amerob", "amegfi"]);
+        assert_eq!(codes("thrushes"), vec!["veery", "amerob"]);
+        assert_eq!(codes("turdus")[0], "amerob");
+        assert_eq!(codes("goldfnch"), vec!["amegfi"]);
+        assert!(codes("penguin").is_empty());
+
+        let top = &index.search("VEER", 1)[0];
+        assert_eq!(top.field, "banding_code");
+        assert_eq!(top.score, 100);
+    }
+
+    #[test]
+    fn distances() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("robin", "robin"), 0);
+        assert_eq!(normalize("Cooper's  Hawk"), "coopers hawk");
+        assert_eq!(
+            normalize("Black-capped Chickadee"),
+            "black capped chickadee"
+        );
+    }
+
+    #[test]
+    fn json_lookups() {
+        let index = TaxonomyIndex::from_json(JSON).unwrap();
+        assert_eq!(index.len(), 2);
+
+        let robin = index.by_common_name("american robin").unwrap();
+        assert_eq!(robin.sci_name_codes, vec!["TUMI"]);
+
+        let duck = index.by_code("duck").unwrap();
+        assert_eq!(duck.category, "spuh");
+        assert!(duck.banding_codes.is_empty());
+    }
+}
@@ -0,0 +1,500 @@
+// turning the html Wikipedia hands back into something fit for a terminal,
+// either plain text or Markdown. Only the handful of tags that show up in
+// article extracts and search excerpts get any special treatment, everything
+// else is dropped and its text kept
+
+// to_text strips the markup out of html, decoding entities and keeping
+// paragraphs apart with a blank line
+pub fn to_text(html: &str) -> String {
+    render(html, false)
+}
+
+// to_markdown is to_text but keeps emphasis, links, headings and lists
+pub fn to_markdown(html: &str) -> String {
+    render(html, true)
+}
+
+// truncate shortens text to at most max_chars characters. It ends on the
+// last full sentence when that keeps at least half of the room, and
+// otherwise cuts between words and marks the cut with an ellipsis
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    let text = text.trim();
+    if text.chars().count() <= max_chars {
+        return text.to_owned();
+    }
+
+    cut(text, max_chars)
+}
+
+// ellipsize tidies up a fragment that was cut off somewhere unknown, like a
+// search excerpt, so it doesn't end mid word without saying so
+pub fn ellipsize(text: &str) -> String {
+    let text = text.trim();
+    match sentence_end(text, text.len()) {
+        Some(end) if end == text.len() => text.to_owned(),
+        _ => cut(text, text.chars().count()),
+    }
+}
+
+fn cut(text: &str, max_chars: usize) -> String {
+    if max_chars == 0 {
+        return String::new();
+    }
+
+    // byte offset of the first character past the limit
+    let limit = text
+        .char_indices()
+        .nth(max_chars)
+        .map_or(text.len(), |(i, _)| i);
+
+    if let Some(end) = sentence_end(text, limit) {
+        if text[..end].chars().count() * 2 >= max_chars {
+            return text[..end].to_owned();
+        }
+    }
+
+    // leave a character for the ellipsis
+    let limit = text
+        .char_indices()
+        .nth(max_chars - 1)
+        .map_or(text.len(), |(i, _)| i);
+    let kept = match text[..limit].rfind(char::is_whitespace) {
+        // the limit itself falls between words
+        _ if text[limit..].starts_with(char::is_whitespace) => &text[..limit],
+        Some(space) if space > 0 => &text[..space],
+        // one very long word, there's nowhere nice to cut it
+        _ => &text[..limit],
+    };
+
+    let mut cut = kept
+        .trim_end()
+        .trim_end_matches([',', ';', ':', '-', '('])
+        .trim_end()
+        .to_owned();
+    cut.push('…');
+
+    cut
+}
+
+// sentence_end finds the byte offset just past the last sentence ending in
+// text[..limit], counting closing quotes and brackets as part of it. A stop
+// only ends a sentence when followed by whitespace or the end of text, so
+// "3.5 cm" doesn't count
+fn sentence_end(text: &str, limit: usize) -> Option<usize> {
+    let head = &text[..limit];
+
+    let mut end = None;
+    let mut chars = head.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if !matches!(c, '.' | '!' | '?' | '。') {
+            continue;
+        }
+
+        let mut after = i + c.len_utf8();
+        while let Some(&(j, next)) = chars.peek() {
+            if !matches!(next, '"' | '\'' | ')' | ']' | '”' | '’' | '»') {
+                break;
+            }
+            after = j + next.len_utf8();
+            chars.next();
+        }
+
+        let followed_by_space = text[after..].chars().next().is_none_or(char::is_whitespace);
+        if followed_by_space && after <= limit {
+            end = Some(after);
+        }
+    }
+
+    end
+}
+
+// Writer builds up the output a paragraph at a time, collapsing runs of
+// whitespace the way a browser would
+struct Writer {
+    markdown: bool,
+    paragraphs: Vec<Paragraph>,
+    current: String,
+    list_item: bool,
+    // hrefs of the links we're inside of, None for ones without a usable href
+    links: Vec<Option<String>>,
+    // inside script or style, whose contents aren't text
+    hidden: usize,
+}
+
+struct Paragraph {
+    text: String,
+    list_item: bool,
+}
+
+impl Writer {
+    fn new(markdown: bool) -> Self {
+        Self {
+            markdown,
+            paragraphs: vec![],
+            current: String::new(),
+            list_item: false,
+            links: vec![],
+            hidden: 0,
+        }
+    }
+
+    fn text(&mut self, c: char) {
+        if self.hidden > 0 {
+            return;
+        }
+
+        if c.is_whitespace() && c != '\u{a0}' {
+            if !self.current.is_empty() && !self.current.ends_with([' ', '\n']) {
+                self.current.push(' ');
+            }
+            return;
+        }
+
+        if self.markdown && matches!(c, '*' | '_' | '[' | ']' | '`' | '\\') {
+            self.current.push('\\');
+        }
+        self.current.push(c);
+    }
+
+    // markup is written as is, it's never escaped
+    fn markup(&mut self, s: &str) {
+        if self.hidden == 0 && self.markdown {
+            self.current.push_str(s);
+        }
+    }
+
+    fn line_break(&mut self) {
+        let trimmed = self.current.trim_end_matches(' ').len();
+        self.current.truncate(trimmed);
+        if !self.current.is_empty() {
+            self.current.push('\n');
+        }
+    }
+
+    fn end_paragraph(&mut self) {
+        let text = self.current.trim().to_owned();
+        self.current.clear();
+
+        // an item's bullet alone isn't worth keeping
+        if !matches!(text.as_str(), "" | "-" | "•") {
+            self.paragraphs.push(Paragraph {
+                text,
+                list_item: self.list_item,
+            });
+        }
+        self.list_item = false;
+    }
+
+    fn tag(&mut self, tag: Tag) {
+        match (tag.name.as_str(), tag.closing) {
+            ("script" | "style", false) => self.hidden += 1,
+            ("script" | "style", true) => self.hidden = self.hidden.saturating_sub(1),
+            ("br", _) => self.line_break(),
+            ("li", false) => {
+                self.end_paragraph();
+                self.list_item = true;
+                self.current
+                    .push_str(if self.markdown { "- " } else { "• " });
+            }
+            ("h1" | "h2" | "h3" | "h4" | "h5" | "h6", false) => {
+                self.end_paragraph();
+                let level = tag.name[1..].parse().unwrap_or(2);
+                self.markup(&format!("{} ", "#".repeat(level)));
+            }
+            (
+                "p" | "div" | "li" | "ul" | "ol" | "dl" | "dt" | "dd" | "blockquote" | "table"
+                | "tr" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "section" | "figure",
+                _,
+            ) => self.end_paragraph(),
+            ("b" | "strong", _) => self.markup("**"),
+            ("i" | "em", _) => self.markup("*"),
+            ("a", false) => {
+                // relative links point into the wiki we got the html from,
+                // which we don't know here
+                let href = tag
+                    .href
+                    .filter(|h| h.starts_with("https://") || h.starts_with("http://"));
+                if href.is_some() {
+                    self.markup("[");
+                }
+                self.links.push(href);
+            }
+            ("a", true) => {
+                if let Some(Some(href)) = self.links.pop() {
+                    self.markup(&format!("]({})", href));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.end_paragraph();
+
+        let mut out = String::new();
+        let mut last_was_item = false;
+        for p in self.paragraphs {
+            if !out.is_empty() {
+                // a list reads as one block, so its items only get a newline
+                out.push_str(if p.list_item && last_was_item {
+                    "\n"
+                } else {
+                    "\n\n"
+                });
+            }
+            out.push_str(&p.text);
+            last_was_item = p.list_item;
+        }
+
+        out
+    }
+}
+
+// Tag is the little we care about in a start or end tag
+struct Tag {
+    name: String,
+    closing: bool,
+    href: Option<String>,
+}
+
+fn render(html: &str, markdown: bool) -> String {
+    let mut w = Writer::new(markdown);
+
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some(after) = rest.strip_prefix("<!--") {
+                rest = after.find("-->").map_or("", |end| &after[end + 3..]);
+                continue;
+            }
+            if let Some((tag, len)) = parse_tag(rest) {
+                w.tag(tag);
+                rest = &rest[len..];
+                continue;
+            }
+            // a < that doesn't start a tag is just text, e.g. "< 10 g"
+        }
+
+        if c == '&' {
+            if let Some((decoded, len)) = decode_entity(rest) {
+                w.text(decoded);
+                rest = &rest[len..];
+                continue;
+            }
+        }
+
+        w.text(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    w.finish()
+}
+
+// parse_tag reads the tag at the start of s, handing back the tag and how
+// many bytes it takes up. Quoted attribute values may contain >
+fn parse_tag(s: &str) -> Option<(Tag, usize)> {
+    let inner = &s[1..];
+    let closing = inner.starts_with('/');
+    let body = inner.trim_start_matches('/');
+    let body_start = s.len() - body.len();
+
+    let first = body.chars().next()?;
+    if !first.is_ascii_alphabetic() && first != '!' {
+        return None;
+    }
+
+    let mut quote = None;
+    let mut end = None;
+    for (i, c) in body.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => {
+                end = Some(i);
+                break;
+            }
+            (None, '<') => return None,
+            _ => {}
+        }
+    }
+    let end = end?;
+
+    let body = &body[..end];
+    let name: String = body
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric())
+        .collect::<String>()
+        .to_lowercase();
+    let href = match name.as_str() {
+        "a" => attribute(body, "href"),
+        _ => None,
+    };
+
+    Some((
+        Tag {
+            name,
+            closing,
+            href,
+        },
+        body_start + end + 1,
+    ))
+}
+
+// attribute pulls a quoted attribute's value out of a tag's body, decoding
+// any entities in it
+fn attribute(body: &str, name: &str) -> Option<String> {
+    // only ASCII is lowercased so offsets into lower line up with body, full
+    // lowercasing changes the length of characters like 'İ'
+    let lower = body.to_ascii_lowercase();
+    let pattern = format!("{}=", name);
+
+    let mut from = 0;
+    while let Some(found) = lower[from..].find(&pattern) {
+        let at = from + found;
+        from = at + pattern.len();
+        // href= inside data-href= isn't the same attribute
+        if at > 0 && !body[..at].ends_with(char::is_whitespace) {
+            continue;
+        }
+
+        let value = &body[from..];
+        let quote = value.chars().next()?;
+        let raw = match quote {
+            '"' | '\'' => &value[1..value[1..].find(quote)? + 1],
+            _ => value.split(char::is_whitespace).next()?,
+        };
+        return Some(to_text(raw));
+    }
+
+    None
+}
+
+// decode_entity reads a character reference like &amp; &#39; or &#x27; at the
+// start of s, handing back the character and how many bytes it takes up.
+// Anything unrecognised is left for the caller to print as is
+fn decode_entity(s: &str) -> Option<(char, usize)> {
+    let end = s.char_indices().take(12).find(|&(_, c)| c == ';')?.0;
+    let name = &s[1..end];
+
+    let c = match name.strip_prefix('#') {
+        Some(num) => {
+            let code = match num.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => num.parse().ok()?,
+            };
+            char::from_u32(code)?
+        }
+        None => match name {
+            "amp" => '&',
+            "lt" => '<',
+            "gt" => '>',
+            "quot" => '"',
+            "apos" => '\'',
+            "nbsp" => '\u{a0}',
+            "ndash" => '–',
+            "mdash" => '—',
+            "hellip" => '…',
+            "lsquo" => '‘',
+            "rsquo" => '’',
+            "ldquo" => '“',
+            "rdquo" => '”',
+            "laquo" => '«',
+            "raquo" => '»',
+            "deg" => '°',
+            "times" => '×',
+            "minus" => '−',
+            "middot" => '·',
+            "shy" => '\u{ad}',
+            _ => return None,
+        },
+    };
+
+    Some((c, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(
+            to_text("<span class=\"searchmatch\">Robin</span>&#39;s eggs &amp; nests"),
+            "Robin's eggs & nests"
+        );
+        assert_eq!(
+            to_text("<p>Weighs &lt; 80 g.</p>\n<p>Sings <b>at <i>dawn</i></b>.</p>"),
+            "Weighs < 80 g.\n\nSings at dawn."
+        );
+        // a < that doesn't open a tag stays
+        assert_eq!(to_text("wingspan < 40 cm"), "wingspan < 40 cm");
+        assert_eq!(to_text("a<!-- note -->b &bogus; c"), "ab &bogus; c");
+        assert_eq!(
+            to_text("<ul><li>Eggs</li><li>Nest</li></ul><p>Done</p>"),
+            "• Eggs\n• Nest\n\nDone"
+        );
+    }
+
+    #[test]
+    fn markdown() {
+        assert_eq!(
+            to_markdown(
+                "<p>The <b>American robin</b> (<i>Turdus migratorius</i>) is a \
+                 <a href=\"https://en.wikipedia.org/wiki/Thrush_(bird)\">thrush</a>.</p>"
+            ),
+            "The **American robin** (*Turdus migratorius*) is a \
+             [thrush](https://en.wikipedia.org/wiki/Thrush_(bird))."
+        );
+        // relative links keep their text only, and text that looks like
+        // markup is escaped
+        assert_eq!(
+            to_markdown("<a href=\"./Bird\">bird_watching</a> *"),
+            "bird\\_watching \\*"
+        );
+    }
+
+    #[test]
+    fn link_attributes() {
+        // attributes before href with characters that change length when
+        // lowercased
+        assert_eq!(
+            to_markdown("<a title=\"\u{212a}\" é HREF=\"https://x.org\">z</a>"),
+            "[z](https://x.org)"
+        );
+        assert_eq!(
+            to_markdown(
+                "<a title=\"İzmir\" href=\"https://en.wikipedia.org/wiki/Izmir\">Izmir</a>"
+            ),
+            "[Izmir](https://en.wikipedia.org/wiki/Izmir)"
+        );
+    }
+
+    #[test]
+    fn truncating() {
+        let text = "The robin is a thrush. It sings at dawn and eats worms.";
+
+        assert_eq!(truncate(text, 100), text);
+        assert_eq!(truncate(text, 40), "The robin is a thrush.");
+        // a sentence that would leave most of the room unused gives way to
+        // cutting between words
+        assert_eq!(
+            truncate(text, 50),
+            "The robin is a thrush. It sings at dawn and eats…"
+        );
+        assert!(truncate(text, 50).chars().count() <= 50);
+        assert_eq!(truncate("Robin", 3), "Ro…");
+        // a decimal point isn't the end of a sentence
+        assert_eq!(truncate("Weighs 77.5 g on average", 16), "Weighs 77.5 g…");
+
+        assert_eq!(
+            ellipsize("About 29% of the robin's range is,"),
+            "About 29% of the robin's range…"
+        );
+        assert_eq!(
+            ellipsize("It sings at dawn. Robins eat"),
+            "It sings at dawn."
+        );
+        assert_eq!(ellipsize("It sings."), "It sings.");
+    }
+}
@@ -0,0 +1,202 @@
+// wire types shared between the birdme server and cli so that changing a
+// field breaks the build on both sides instead of failing at runtime
+use serde::{Deserialize, Serialize};
+
+pub mod html;
+
+// Bird is a single species as returned by GET /birds/<region>
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Bird {
+    pub name: String,
+    pub scientific_name: String,
+    // eBird species code, e.g. "amerob". Species pages live at
+    // https://ebird.org/species/<code>
+    pub species_code: String,
+    // position in the eBird taxonomy, sorting by it gives field guide order
+    pub taxon_order: f32,
+    pub order: String,
+    pub family_name: String,
+    pub family_scientific_name: String,
+    // eBird category: species, issf, spuh, slash, hybrid, intergrade,
+    // domestic or form
+    pub category: String,
+    // 4 letter banding codes, e.g. "AMRO"
+    pub banding_codes: Vec<String>,
+    // the bird's Wikipedia article. This and the rest of the fields below
+    // are missing when the article couldn't be looked up
+    pub link: Option<String>,
+    // lead section of the article as plain text
+    pub blurb: Option<String>,
+    // the same as html, for rendering with formatting, see html::to_markdown.
+    // Missing when only a search excerpt was found
+    pub blurb_html: Option<String>,
+    // the article's short description, e.g. "Species of bird"
+    pub description: Option<String>,
+    // lead image of the article
+    pub image: Option<Image>,
+}
+
+// Image is a picture hosted on Wikimedia
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Image {
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+// BirdsQuery shapes the random birds asked for with GET /birds/<region>.
+// Anything left out falls back to the server's defaults, and the same seed
+// gives back the same birds, e.g. so a whole class sees the same five
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[cfg_attr(feature = "rocket", derive(rocket::FromForm))]
+pub struct BirdsQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<usize>,
+    // part of a family's name or its code, e.g. "warbler"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>,
+    // eBird category to keep, e.g. "species"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    // favour birds seen lately over the region's all time list
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weighted: Option<bool>,
+    // 0 favours common birds and 1 uncommon ones, implies weighted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rarity: Option<f64>,
+}
+
+// TodaysBird is the bird of the day for a region as returned by
+// GET /birds/<region>/today
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct TodaysBird {
+    // the UTC day the bird was picked for, e.g. "2024-01-27"
+    pub date: String,
+    #[serde(flatten)]
+    pub bird: Bird,
+}
+
+// SpeciesDetail is everything birdme knows about one species, as returned by
+// GET /species/<code>
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SpeciesDetail {
+    #[serde(flatten)]
+    pub bird: Bird,
+    // recent sightings in the region asked about, if one was
+    pub sightings: Option<Sightings>,
+}
+
+// Sightings sums up recent reports of a species in a region
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Sightings {
+    pub region: String,
+    // how many days back the sightings go
+    pub days: u8,
+    // number of locations the species was reported at
+    pub locations: usize,
+    // total birds counted across those reports, leaving out "X" counts
+    pub birds_counted: u32,
+    // local date and time of the latest report, e.g. "2024-01-27 08:15"
+    pub latest: Option<String>,
+}
+
+// Frequency is how often a species turns up in a region through the year, as
+// returned by GET /species/<code>/frequency
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Frequency {
+    pub species_code: String,
+    pub name: String,
+    pub region: String,
+    // the years the days were sampled from, oldest first
+    pub years: Vec<i32>,
+    // how many days were looked at in each week of each year
+    pub samples_per_week: u32,
+    // how many sampled days each week's share is out of, samples_per_week
+    // times the number of years. A share can only move in steps of one over
+    // this, so it's the precision of the chart
+    pub days_per_week: u32,
+    // 48 weeks, four to a month like eBird's bar charts. Each is the share of
+    // sampled days, from 0 to 1, the species was reported on
+    pub weeks: Vec<f32>,
+}
+
+// Observation is a single sighting as returned by GET /birds/<region>/recent
+// and GET /birds/<region>/notable
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Observation {
+    pub species_code: String,
+    pub name: String,
+    pub scientific_name: String,
+    // number of birds reported, missing when the observer only marked "X"
+    pub count: Option<u32>,
+    pub location: String,
+    pub lat: f64,
+    pub lng: f64,
+    // local date and time of the sighting, e.g. "2024-01-27 08:15"
+    pub observed_on: String,
+    // whether the sighting passed eBird's filters or was accepted by a reviewer
+    pub valid: bool,
+    // whether a regional reviewer has looked at the sighting
+    pub reviewed: bool,
+}
+
+// Hotspot is a public birding location as returned by GET /hotspots/near,
+// GET /hotspots/<region> and GET /hotspots/info/<loc_id>
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Hotspot {
+    // eBird location id, e.g. "L109516"
+    pub id: String,
+    pub name: String,
+    pub country: String,
+    pub subnational1: String,
+    pub lat: f64,
+    pub lng: f64,
+    // number of species ever reported at the hotspot
+    pub species_count: Option<u32>,
+    // local date and time of the latest sighting, e.g. "2024-01-27 08:15"
+    pub latest_observation: Option<String>,
+}
+
+// Region is an eBird region as returned by GET /regions and
+// GET /regions/<code>
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Region {
+    // eBird region code, e.g. "US-NY"
+    pub code: String,
+    pub name: String,
+}
+
+// SearchResult is a single taxonomy match as returned by GET /search, best
+// matches first
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SearchResult {
+    pub name: String,
+    pub scientific_name: String,
+    pub species_code: String,
+    pub family_name: String,
+    pub banding_codes: Vec<String>,
+    // which field the query matched: species_code, banding_code,
+    // common_name, scientific_name or family
+    pub matched: String,
+    // how good the match was, higher is better. Only meaningful relative to
+    // the other results of the same search
+    pub score: u32,
+}
+
+// CacheCleared is returned by DELETE /admin/cache
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CacheCleared {
+    // number of cached entries that were dropped
+    pub removed: usize,
+}
+
+// ErrorBody is the JSON payload the server sends alongside an error status
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ErrorBody {
+    // short machine readable name of the error, e.g. "rate_limited"
+    pub error: String,
+    // human readable explanation that can be shown to the user
+    pub message: String,
+}